@@ -11,7 +11,7 @@ use std::time::Duration;
 ///
 /// - [`Error`] of kind [`ErrorKind::InvalidInput`] if `ptr` is null.
 /// - [`Error`] of kind [`ErrorKind::InvalidData`] containing a [`Utf8Error`] if the string does
-/// not contain valid utf-8 data.
+///   not contain valid utf-8 data.
 ///
 /// # Safety
 ///
@@ -55,8 +55,8 @@ pub struct PamAuthenticator {
 
 impl PamAuthenticator {
     pub fn new(user: User, rules: Rules) -> Result<Self> {
-        let mut handle =
-            pam::Handle::start(SERVICE_NAME, &user.name[..], Box::new(pam_conversation))?;
+        let service = rules.pam_service.as_deref().unwrap_or(SERVICE_NAME);
+        let mut handle = pam::Handle::start(service, &user.name[..], Box::new(pam_conversation))?;
 
         let mut items = handle.items();
         items.set_request_user(&user.name[..])?;
@@ -12,15 +12,26 @@ pub(crate) mod defaults {
     pub const fn timeout() -> Option<Duration> {
         Some(Duration::from_secs(120))
     }
+
+    #[inline]
+    pub const fn service() -> Option<AuthService> {
+        None
+    }
+
+    #[inline]
+    pub const fn pam_service() -> Option<String> {
+        None
+    }
 }
 
 /// All supported authentication services.
-#[allow(unused)]
+///
+/// Services may be named in configurations even if support for them was not compiled in. See
+/// [`AuthService::is_available`].
 #[non_exhaustive]
-#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, Copy)]
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, Copy, PartialEq, Eq)]
 pub enum AuthService {
-    /// Authentication using PAM.
-    #[cfg(feature = "pam")]
+    /// Authentication using PAM. Requires the `pam` feature.
     Pam,
     /// Authentication using the system password database.
     Pwd,
@@ -36,6 +47,17 @@ impl Default for AuthService {
     }
 }
 
+impl AuthService {
+    /// Check if support for this service was compiled in.
+    #[must_use]
+    pub const fn is_available(self) -> bool {
+        match self {
+            Self::Pam => cfg!(feature = "pam"),
+            Self::Pwd => true,
+        }
+    }
+}
+
 /// Predefined rules for a user session.
 #[readonly::make]
 #[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
@@ -44,12 +66,73 @@ pub struct Rules {
     #[serde(with = "utils::timeout_serializer")]
     #[serde(default = "defaults::timeout")]
     timeout: Option<Duration>,
+    /// Authentication service to use instead of the global one.
+    #[serde(default = "defaults::service")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub service: Option<AuthService>,
+    /// Name of the PAM service to use instead of the global one. This names a file in
+    /// `/etc/pam.d/`.
+    #[serde(rename = "pam-service")]
+    #[serde(default = "defaults::pam_service")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pam_service: Option<String>,
 }
 
 impl Default for Rules {
     fn default() -> Self {
         Self {
             timeout: defaults::timeout(),
+            service: defaults::service(),
+            pam_service: defaults::pam_service(),
+        }
+    }
+}
+
+impl Rules {
+    /// Combine these rules with another, keeping the strictest of both.
+    ///
+    /// The services of these rules are preferred over those of `other`.
+    #[must_use]
+    pub fn strictest(&self, other: &Self) -> Self {
+        Self {
+            timeout: match (self.timeout, other.timeout) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            },
+            service: self.service.or(other.service),
+            pam_service: self
+                .pam_service
+                .clone()
+                .or_else(|| other.pam_service.clone()),
         }
     }
+
+    /// Use a PAM service if these rules don't name one.
+    #[must_use]
+    pub fn or_pam_service(&self, name: Option<&str>) -> Self {
+        Self {
+            pam_service: self
+                .pam_service
+                .clone()
+                .or_else(|| name.map(ToOwned::to_owned)),
+            ..self.clone()
+        }
+    }
+
+    /// Check that the PAM service name, if any, names a file in `/etc/pam.d/`.
+    pub fn validate(&self) -> core::result::Result<(), String> {
+        match &self.pam_service {
+            Some(s) => validate_pam_service(s),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Check that a PAM service name names a file in `/etc/pam.d/`.
+pub fn validate_pam_service(name: &str) -> core::result::Result<(), String> {
+    if name.is_empty() || name.contains('/') || name == "." || name == ".." {
+        return Err(format!("invalid PAM service name `{}`", name));
+    }
+
+    Ok(())
 }
@@ -0,0 +1,193 @@
+//! Linux capabilities of command processes.
+//!
+//! A policy may grant a set of capabilities instead of a full identity, such as binding to
+//! privileged ports as an unprivileged target. The capabilities are kept while the child process
+//! changes its user, and raised in the ambient set, which is preserved when the command is
+//! executed. All other capabilities are dropped from the bounding set, so a root target, or a
+//! `setuid` program run by the command, can't gain more.
+
+use std::io;
+
+use nix::libc;
+
+/// Names of capabilities, indexed by their number, without the `CAP_` prefix.
+const NAMES: &[&str] = &[
+    "chown",
+    "dac_override",
+    "dac_read_search",
+    "fowner",
+    "fsetid",
+    "kill",
+    "setgid",
+    "setuid",
+    "setpcap",
+    "linux_immutable",
+    "net_bind_service",
+    "net_broadcast",
+    "net_admin",
+    "net_raw",
+    "ipc_lock",
+    "ipc_owner",
+    "sys_module",
+    "sys_rawio",
+    "sys_chroot",
+    "sys_ptrace",
+    "sys_pacct",
+    "sys_admin",
+    "sys_boot",
+    "sys_nice",
+    "sys_resource",
+    "sys_time",
+    "sys_tty_config",
+    "mknod",
+    "lease",
+    "audit_write",
+    "audit_control",
+    "setfcap",
+    "mac_override",
+    "mac_admin",
+    "syslog",
+    "wake_alarm",
+    "block_suspend",
+    "audit_read",
+    "perfmon",
+    "bpf",
+    "checkpoint_restore",
+];
+
+/// `_LINUX_CAPABILITY_VERSION_3`, with 64 bit sets split into two words.
+const VERSION: u32 = 0x2008_0522;
+
+/// `struct __user_cap_header_struct`.
+#[repr(C)]
+struct Header {
+    version: u32,
+    pid: libc::c_int,
+}
+
+/// `struct __user_cap_data_struct`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Data {
+    effective: u32,
+    permitted: u32,
+    inheritable: u32,
+}
+
+/// Get the number of a capability by its name, such as `CAP_NET_BIND_SERVICE` or
+/// `net_bind_service`.
+#[must_use]
+pub fn from_name(name: &str) -> Option<u32> {
+    let name = name.to_ascii_lowercase();
+    let name = name.strip_prefix("cap_").unwrap_or(&name);
+
+    NAMES.iter().position(|n| *n == name).map(|c| c as u32)
+}
+
+/// A set of capabilities.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Set(u64);
+
+impl Set {
+    /// Get the set of capabilities with the given names.
+    ///
+    /// # Errors
+    ///
+    /// Returns a description of the first name that is not a known capability.
+    pub fn from_names(names: &[String]) -> core::result::Result<Self, String> {
+        names
+            .iter()
+            .try_fold(Self::default(), |set, name| match from_name(name) {
+                Some(c) => Ok(Self(set.0 | 1 << c)),
+                None => Err(format!("unknown capability {}", name)),
+            })
+    }
+
+    /// Check if a capability is in this set.
+    #[must_use]
+    pub const fn contains(&self, cap: u32) -> bool {
+        cap < 64 && self.0 & 1 << cap != 0
+    }
+
+    /// Keep capabilities when this process changes its user, and drop all capabilities outside
+    /// this set from the bounding set.
+    ///
+    /// This is called in the child process before it changes its user, which requires
+    /// `CAP_SETPCAP`. Like [`Set::raise`], it never allocates.
+    #[allow(unsafe_code)]
+    pub fn keep(&self) -> io::Result<()> {
+        let one: libc::c_ulong = 1;
+        if unsafe { libc::prctl(libc::PR_SET_KEEPCAPS, one) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // The kernel may know fewer capabilities than 64, which can't be dropped
+        for cap in (0..64).filter(|c| !self.contains(*c)) {
+            let cap = libc::c_ulong::from(cap);
+            if unsafe { libc::prctl(libc::PR_CAPBSET_DROP, cap) } != 0 {
+                let err = io::Error::last_os_error();
+                if err.raw_os_error() == Some(libc::EINVAL) {
+                    break;
+                }
+                return Err(err);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Set the capabilities of this process to this set, and raise them in the ambient set.
+    ///
+    /// This is called in the child process after it changed its user, following [`Set::keep`].
+    #[allow(unsafe_code)]
+    pub fn raise(&self) -> io::Result<()> {
+        let mut header = Header {
+            version: VERSION,
+            pid: 0,
+        };
+        let data = [self.0 as u32, (self.0 >> 32) as u32].map(|set| Data {
+            effective: set,
+            permitted: set,
+            inheritable: set,
+        });
+
+        let header = &mut header as *mut Header;
+        if unsafe { libc::syscall(libc::SYS_capset, header, data.as_ptr()) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let (zero, raise) = (
+            0 as libc::c_ulong,
+            libc::PR_CAP_AMBIENT_RAISE as libc::c_ulong,
+        );
+        for cap in (0..64).filter(|c| self.contains(*c)) {
+            let cap = libc::c_ulong::from(cap);
+            if unsafe { libc::prctl(libc::PR_CAP_AMBIENT, raise, cap, zero, zero) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_names() {
+        assert_eq!(from_name("CAP_NET_BIND_SERVICE"), Some(10));
+        assert_eq!(from_name("dac_read_search"), Some(2));
+        assert_eq!(from_name("net_bind"), None);
+
+        let set = Set::from_names(&["cap_chown".into(), "CAP_BPF".into()]).unwrap();
+        assert!(set.contains(0) && set.contains(39));
+        assert!(!set.contains(10) && !set.contains(64));
+
+        assert_eq!(
+            Set::from_names(&["sys_admin".into(), "root".into()]),
+            Err("unknown capability root".into())
+        );
+    }
+}
@@ -1,31 +1,48 @@
 //! This holds everything together.
 
 use std::cell::Cell;
+use std::collections::HashMap;
+use std::ffi::CString;
 use std::fs;
 use std::io;
 use std::os::unix::process::{parent_id, CommandExt};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
-use nix::unistd::{getuid, User};
+use nix::unistd::{self, getuid, User};
 
 use crate::auth;
+use crate::capabilities;
 use crate::config::Config;
+use crate::decision::{Decision, Request, Rule};
+use crate::mapping::Subject;
+use crate::noexec;
 use crate::options::*;
-use crate::permits::Permits;
+use crate::origin::Origin;
 use crate::policy::Policy;
 use crate::prelude::*;
+use crate::schedule::Clock;
 use crate::session::{State, UserSession};
 
-pub struct App {
-    session: UserSession,
-    permits: Permits,
+pub struct App<'a> {
+    config: &'a Config,
+    /// The invoking user.
+    user: User,
+    /// Groups the invoking user is a member of.
+    groups: Vec<Subject>,
+    /// Name of this host.
+    host: String,
+    /// The time `mk` was invoked at, so that all decisions see the same schedules.
+    clock: Clock,
+    /// Where `mk` was invoked from.
+    origin: Origin,
+    /// Session used to run commands, once one was started.
+    session: Option<UserSession>,
 }
 
-impl App {
-    pub fn new(cfg: &Config) -> Result<Self> {
-        let uid = getuid();
-        let user = match User::from_uid(uid)? {
+impl<'a> App<'a> {
+    pub fn new(cfg: &'a Config) -> Result<Self> {
+        let user = match User::from_uid(getuid())? {
             Some(u) => u,
             None => {
                 return Err(io::Error::new(
@@ -36,58 +53,174 @@ impl App {
             }
         };
 
-        // Ignore configs if the user is root
-        if uid.is_root() {
-            let policy = Policy::root();
-            let session = UserSession::new(
-                auth::new(user, cfg.service, policy.auth.clone())?,
-                policy.session.clone(),
-            );
-
-            return Ok(Self {
-                session,
-                permits: policy.permits.clone(),
-            });
-        }
+        let groups = utils::get_user_groups(&user)?
+            .into_iter()
+            .map(Subject::from)
+            .collect();
+
+        Ok(Self {
+            config: cfg,
+            user,
+            groups,
+            host: utils::get_host_name()?,
+            clock: Clock::system(),
+            origin: Origin::current(),
+            session: None,
+        })
+    }
 
-        // Find a suitable policy and create a session
-        if let Some(policy) = cfg.get_user_policy(&user)? {
-            let session_state = Self::recover_session_state_or_new(&user)?;
+    /// Decide whether the user may run an executable with the given arguments as a target,
+    /// recording each step taken in `trace`.
+    ///
+    /// `path` must be the absolute path of the executable. See [`Config::explain`].
+    pub fn evaluate(
+        &self,
+        target: &User,
+        path: &Path,
+        args: &[String],
+        trace: &mut Vec<String>,
+    ) -> Result<Decision<'a>> {
+        let target_groups: Vec<_> = utils::get_user_groups(target)?
+            .into_iter()
+            .map(Subject::from)
+            .collect();
+
+        let request = Request {
+            invoker: &Subject::from(&self.user),
+            groups: &self.groups,
+            target: &Subject::from(target),
+            target_groups: &target_groups,
+            command: path,
+            args,
+            host: &self.host,
+            clock: &self.clock,
+            origin: &self.origin,
+        };
 
-            let session = UserSession::with_state(
-                auth::new(user, cfg.service, policy.auth.clone())?,
-                policy.session.clone(),
-                session_state,
-            );
+        Ok(self.config.explain(&request, trace))
+    }
 
-            return Ok(Self {
-                session,
-                permits: policy.permits.clone(),
-            });
+    /// Find a command and decide whether the user may run it with the given arguments as a
+    /// target, recording each step taken in `trace`.
+    ///
+    /// Commands are found inside the chroot of the policy that decides whether they may run. If
+    /// policies were merged, the chroot of each is tried in turn, and a command found in one is
+    /// only permitted by a policy with that chroot. Otherwise, the first denied decision is
+    /// returned.
+    pub fn decide(
+        &self,
+        target: &User,
+        command: &str,
+        args: &[String],
+        trace: &mut Vec<String>,
+    ) -> Result<(PathBuf, Decision<'a>)> {
+        let (mut denied, mut found) = (None, false);
+
+        for root in self.chroots()? {
+            let path = match &root {
+                Some(r) => utils::find_executable_in(r, command),
+                None => utils::find_executable(command),
+            };
+            let path = match path {
+                Some(p) => p,
+                None => continue,
+            };
+            found = true;
+
+            let mut steps = Vec::new();
+            let decision = self.evaluate(target, &path, args, &mut steps)?;
+            let chroot = decision
+                .policy
+                .as_ref()
+                .and_then(|p| p.policy.process.chroot.as_ref());
+
+            if decision.is_allowed() {
+                if chroot == root.as_ref() {
+                    trace.extend(steps);
+                    return Ok((path, decision));
+                }
+            } else if denied.is_none() {
+                denied = Some((path, decision, steps));
+            }
         }
 
-        Err(io::Error::new(
-            io::ErrorKind::PermissionDenied,
-            "no defined policy for this user",
-        )
-        .into())
-    }
-
-    /// Check if a user is allowed to run as a target.
-    pub fn check(&self, target: &User) -> Result<()> {
-        // ᕙ(⇀‸↼‵‵)ᕗ
-        if !(self.session.get_user() == target
-            || self.permits.targets.contains(&target.name)
-            || self.permits.all_targets)
-        {
-            return Err(io::Error::new(
+        match denied {
+            Some((path, decision, steps)) => {
+                trace.extend(steps);
+                Ok((path, decision))
+            }
+            None if found => Err(io::Error::new(
                 io::ErrorKind::PermissionDenied,
-                format!("not permitted to run as user {}", target.name),
+                format!(
+                    "not permitted to run {} in the chroot it was found in",
+                    command
+                ),
             )
-            .into());
+            .into()),
+            None => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("command not found: {}", command),
+            )
+            .into()),
         }
+    }
 
-        Ok(())
+    /// Find a command and check if the user is allowed to run it with the given arguments as a
+    /// target. See [`App::decide`].
+    pub fn check(
+        &self,
+        target: &User,
+        command: &str,
+        args: &[String],
+    ) -> Result<(PathBuf, Decision<'a>)> {
+        let (path, decision) = self.decide(target, command, args, &mut Vec::new())?;
+
+        let denied = |message: String| -> Result<(PathBuf, Decision<'a>)> {
+            Err(io::Error::new(io::ErrorKind::PermissionDenied, message).into())
+        };
+
+        match &decision.rule {
+            Rule::NoPolicy(Some(reason)) => denied(reason.clone()),
+            Rule::NoPolicy(None) => denied("no defined policy for this user".into()),
+            Rule::DeniedTarget(rule) => denied(format!(
+                "not permitted to run as user {} (denied by deny-targets rule `{}`)",
+                target.name, rule
+            )),
+            // ᕙ(⇀‸↼‵‵)ᕗ
+            Rule::UnpermittedTarget => {
+                denied(format!("not permitted to run as user {}", target.name))
+            }
+            Rule::DeniedCommand(rule) => denied(format!(
+                "not permitted to run {} (denied by deny-commands rule `{}`)",
+                path.display(),
+                rule
+            )),
+            Rule::UnpermittedCommand => denied(format!("not permitted to run {}", path.display())),
+            Rule::Root | Rule::Permitted { .. } => Ok((path, decision)),
+        }
+    }
+
+    /// Start a session for the invoking user with the rules of a policy, or continue the current
+    /// one.
+    fn session(&mut self, policy: &Policy) -> Result<&mut UserSession> {
+        if self.session.is_none() {
+            let auth = auth::new(
+                self.user.clone(),
+                self.config.service_for(policy),
+                self.config.auth_rules_for(policy),
+            )?;
+
+            // Sessions of root are never recovered
+            let state = if self.user.uid.is_root() {
+                State::new()
+            } else {
+                Self::recover_session_state_or_new(&self.user)?
+            };
+
+            self.session = Some(UserSession::with_state(auth, policy.session.clone(), state));
+        }
+
+        Ok(self.session.as_mut().unwrap())
     }
 
     /// Run the appropriate method for given options.
@@ -98,6 +231,7 @@ impl App {
     pub fn run(&mut self, options: MkOptions) -> Result<Option<i32>> {
         let res = match options {
             MkOptions::Command(cmd) => self.exec(cmd),
+            MkOptions::Explain(cmd) => self.explain(&cmd),
             MkOptions::Text(s) => {
                 println!("{}", s);
                 Ok(None)
@@ -106,11 +240,69 @@ impl App {
         };
 
         // we'll probably log this later
-        let _ = Self::save_session_state(&self.session);
+        if let Some(session) = &self.session {
+            let _ = Self::save_session_state(session);
+        }
 
         res
     }
 
+    /// Print how the decision to run a command with the given `options` would be made, without
+    /// running it.
+    ///
+    /// # Returns
+    ///
+    /// `0` if the command would be permitted, `1` otherwise.
+    pub fn explain(&self, options: &CommandOptions) -> Result<Option<i32>> {
+        let mut trace = Vec::new();
+        let (path, decision) =
+            self.decide(&options.target, &options.command, &options.args, &mut trace)?;
+
+        println!(
+            "{} running {} as {}:",
+            self.user.name,
+            path.display(),
+            options.target.name
+        );
+
+        for step in trace {
+            println!("  {}", step);
+        }
+
+        Ok(Some(if decision.is_allowed() { 0 } else { 1 }))
+    }
+
+    /// Chroots commands may be found in, `None` being the root directory: that of each policy
+    /// the invoking user is mapped to, in the order they are resolved, without duplicates.
+    fn chroots(&self) -> Result<Vec<Option<PathBuf>>> {
+        // Configurations are ignored for root
+        if self.user.uid.is_root() {
+            return Ok(vec![None]);
+        }
+
+        let resolved = self.config.resolve_user(
+            &Subject::from(&self.user),
+            &self.groups,
+            &self.host,
+            &self.clock,
+            &self.origin,
+        )?;
+
+        let mut roots = Vec::new();
+        for m in resolved.iter().flat_map(|r| &r.mappings) {
+            if let Some(p) = self.config.policies.get(&m.policy) {
+                if !roots.contains(&p.process.chroot) {
+                    roots.push(p.process.chroot.clone());
+                }
+            }
+        }
+        if roots.is_empty() {
+            roots.push(None);
+        }
+
+        Ok(roots)
+    }
+
     /// Execute a command with the given `options`.
     ///
     /// # Returns
@@ -120,18 +312,108 @@ impl App {
         let exit = Cell::new(None);
         let target = &options.target;
 
-        self.check(target)?;
-        self.session.run(
+        let (path, decision) = self.check(target, &options.command, &options.args)?;
+        let policy = match &decision.policy {
+            Some(p) => &p.policy,
+            None => unreachable!("allowed decisions always have a policy"),
+        };
+
+        // Variables that are not valid unicode can never be kept
+        let vars: HashMap<_, _> = std::env::vars_os()
+            .filter_map(|(k, v)| Some((k.into_string().ok()?, v.into_string().ok()?)))
+            .collect();
+        // Root keeps their whole environment, as with configurations ignored
+        let preserve = match (&decision.rule, &options.preserve_env) {
+            (Rule::Root, _) => {
+                let mut all: Vec<_> = vars.keys().cloned().collect();
+                all.sort();
+                all
+            }
+            (_, Some(p)) => p.clone(),
+            (_, None) => Vec::new(),
+        };
+        let env = policy.env.build(target, &vars, &preserve)?;
+
+        let dir = policy.process.working_dir(options.chdir.as_deref())?;
+        if let Some(root) = &policy.process.chroot {
+            utils::check_secure_path(&utils::normalize_path(root))?;
+        }
+
+        let noexec = policy.process.noexec
+            || matches!(&decision.rule, Rule::Permitted { command: Some(c), .. } if c.noexec);
+        let caps = if policy.process.capabilities.is_empty() {
+            None
+        } else {
+            Some(
+                capabilities::Set::from_names(&policy.process.capabilities)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+            )
+        };
+
+        let program = if noexec {
+            Some(noexec::Program::new(&path, &options.args, &env)?)
+        } else {
+            None
+        };
+
+        self.session(policy)?.run(
             target,
+            &path,
+            &options.args,
             Box::new(|| -> Result<()> {
-                let mut command = Command::new(&options.command[..]);
-
-                command.uid(options.target.uid.as_raw());
-                command.gid(options.target.gid.as_raw());
+                let mut command = Command::new(&path);
+
+                let process = policy.process.clone();
+                let (uid, gid) = (options.target.uid, options.target.gid);
+
+                // The target's own supplementary groups, looked up before entering the chroot,
+                // whose group database may differ, and before forking, as looking them up is not
+                // async-signal-safe
+                let name = CString::new(&options.target.name[..]).map_err(io::Error::from)?;
+                let groups = unistd::getgrouplist(&name, gid)?;
+
+                // Process attributes and the chroot are applied before dropping privileges, since
+                // the target may not be allowed to apply them itself
+                #[allow(unsafe_code)]
+                unsafe {
+                    command.pre_exec(move || {
+                        process.apply()?;
+
+                        unistd::setgroups(&groups)?;
+
+                        if let Some(root) = &process.chroot {
+                            unistd::chroot(root)?;
+                            unistd::chdir("/")?;
+                        }
+
+                        if let Some(caps) = &caps {
+                            caps.keep()?;
+                        }
+
+                        unistd::setgid(gid)?;
+                        unistd::setuid(uid)?;
+
+                        if let Some(caps) = &caps {
+                            caps.raise()?;
+                        }
+
+                        // As the target, who must be permitted to enter the directory
+                        if let Some(dir) = &dir {
+                            unistd::chdir(dir)?;
+                        }
+
+                        // The filter denies any later `exec`, including the one by `command`
+                        match &program {
+                            Some(p) => Err(p.exec()),
+                            None => Ok(()),
+                        }
+                    });
+                }
 
-                command.args(options.args);
+                command.args(&options.args);
 
-                // TODO: env preservation
+                command.env_clear();
+                command.envs(env);
 
                 if let Some(c) = command.spawn()?.wait()?.code() {
                     let _ = &exit.set(Some(c));
@@ -0,0 +1,579 @@
+//! Listing of a user's permissions.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::io;
+
+use nix::unistd::{getuid, User};
+
+use crate::auth::AuthService;
+use crate::config::{Config, MappingKind, MatchedMapping, ResolvedPolicy};
+use crate::env;
+use crate::mapping::Subject;
+use crate::options::ListOptions;
+use crate::origin::{self, Origin};
+use crate::permits::Permits;
+use crate::policy::Policy;
+use crate::prelude::*;
+use crate::process;
+use crate::schedule::{Clock, Window};
+use crate::session;
+
+/// Conditions under which a policy the user is mapped to applies.
+#[derive(Debug, serde::Serialize)]
+pub struct Conditions<'a> {
+    /// Name of the policy.
+    pub policy: String,
+    /// Host name patterns the policy is restricted to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hosts: Option<&'a [String]>,
+    /// Time from which the policy is valid.
+    #[serde(rename = "valid-from")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub valid_from: Option<String>,
+    /// Time until which the policy is valid.
+    #[serde(rename = "valid-until")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub valid_until: Option<String>,
+    /// Recurring windows of time in which the policy is valid.
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    pub windows: &'a [Window],
+    /// Where the policy may be used from, if it is restricted.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub origin: Option<&'a origin::Rules>,
+}
+
+impl<'a> Conditions<'a> {
+    /// Get the conditions of a policy.
+    fn new(name: &str, policy: &'a Policy) -> Self {
+        Self {
+            policy: name.to_owned(),
+            hosts: policy.hosts.as_deref(),
+            valid_from: policy.valid_from.as_ref().map(ToString::to_string),
+            valid_until: policy.valid_until.as_ref().map(ToString::to_string),
+            windows: &policy.windows,
+            origin: Some(&policy.origin)
+                .filter(|o| o.require_tty || o.ttys.is_some() || !o.allow_ssh),
+        }
+    }
+}
+
+/// The permissions of a user on a host.
+#[derive(Debug, serde::Serialize)]
+pub struct Listing<'a> {
+    /// Name of the user.
+    pub user: String,
+    /// Name of the host.
+    pub host: String,
+    /// Whether the user is root, for whom all configurations are ignored.
+    pub root: bool,
+    /// Mappings the policy was resolved from.
+    pub mappings: Vec<MatchedMapping>,
+    /// Conditions of the policy of each mapping, which are not carried over when policies are
+    /// merged.
+    pub conditions: Vec<Conditions<'a>>,
+    /// Permitted actions, if any policy applies.
+    pub permits: Option<Cow<'a, Permits>>,
+    /// Session rules, if any policy applies.
+    pub session: Option<Cow<'a, session::Rules>>,
+    /// Environment rules of the policy of each mapping, which apply to the commands it permits.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub env: Vec<Cow<'a, env::Rules>>,
+    /// Attributes of command processes of the policy of each mapping, which apply to the commands
+    /// it permits.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub process: Vec<Cow<'a, process::Rules>>,
+    /// Authentication service used with the policy.
+    pub service: AuthService,
+    /// PAM service used with the policy, if it authenticates with PAM.
+    #[serde(rename = "pam-service")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pam_service: Option<String>,
+    /// Why no policy applies, if one was skipped.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl<'a> Listing<'a> {
+    /// List the permissions of a user, as they would be resolved when running a command from
+    /// `origin`.
+    ///
+    /// This never authenticates the user.
+    pub fn new(
+        config: &'a Config,
+        user: &User,
+        host: &str,
+        clock: &Clock,
+        origin: &Origin,
+    ) -> Result<Self> {
+        let groups: Vec<_> = utils::get_user_groups(user)?
+            .into_iter()
+            .map(Subject::from)
+            .collect();
+
+        Self::with_groups(config, &Subject::from(user), &groups, host, clock, origin)
+    }
+
+    /// List the permissions of a user who is a member of `groups`, like [`Listing::new`].
+    pub fn with_groups(
+        config: &'a Config,
+        user: &Subject,
+        groups: &[Subject],
+        host: &str,
+        clock: &Clock,
+        origin: &Origin,
+    ) -> Result<Self> {
+        let mut listing = Self {
+            user: user.name.clone(),
+            host: host.to_owned(),
+            root: user.id == 0,
+            mappings: Vec::new(),
+            conditions: Vec::new(),
+            permits: None,
+            session: None,
+            env: Vec::new(),
+            process: Vec::new(),
+            service: config.service,
+            pam_service: None,
+            reason: None,
+        };
+
+        // Configurations are ignored for root
+        if listing.root {
+            let policy = Policy::root();
+            listing.permits = Some(Cow::Owned(policy.permits.clone()));
+            listing.session = Some(Cow::Owned(policy.session.clone()));
+            listing.env = vec![Cow::Owned(policy.env.clone())];
+            listing.process = vec![Cow::Owned(policy.process.clone())];
+            return Ok(listing);
+        }
+
+        match config.resolve_user(user, groups, host, clock, origin) {
+            Ok(Some(ResolvedPolicy { policy, mappings })) => {
+                listing.conditions = mappings
+                    .iter()
+                    .filter_map(|m| {
+                        Some(Conditions::new(&m.policy, config.policies.get(&m.policy)?))
+                    })
+                    .collect();
+                listing.env = mappings
+                    .iter()
+                    .filter_map(|m| Some(Cow::Borrowed(&config.policies.get(&m.policy)?.env)))
+                    .collect();
+                listing.process = mappings
+                    .iter()
+                    .filter_map(|m| Some(Cow::Borrowed(&config.policies.get(&m.policy)?.process)))
+                    .collect();
+                listing.mappings = mappings;
+                listing.service = config.service_for(&policy);
+                if listing.service == AuthService::Pam {
+                    let rules = config.auth_rules_for(&policy);
+                    let name = rules.pam_service.as_deref().unwrap_or(SERVICE_NAME);
+                    listing.pam_service = Some(name.to_owned());
+                }
+                match policy {
+                    Cow::Borrowed(p) => {
+                        listing.permits = Some(Cow::Borrowed(&p.permits));
+                        listing.session = Some(Cow::Borrowed(&p.session));
+                    }
+                    Cow::Owned(p) => {
+                        listing.permits = Some(Cow::Owned(p.permits.clone()));
+                        listing.session = Some(Cow::Owned(p.session.clone()));
+                    }
+                }
+            }
+            Ok(None) => {}
+            Err(Error::Io(e)) if e.kind() == io::ErrorKind::PermissionDenied => {
+                listing.reason = Some(e.to_string());
+            }
+            Err(e) => return Err(e),
+        }
+
+        Ok(listing)
+    }
+
+    /// List the permissions requested by `options` on this host, at the current time, from where
+    /// this process was invoked.
+    ///
+    /// # Errors
+    ///
+    /// Only root may list the permissions of other users. An [`io::Error`] of kind
+    /// [`io::ErrorKind::PermissionDenied`] is returned otherwise.
+    pub fn from_options(config: &'a Config, options: &ListOptions) -> Result<Self> {
+        let uid = getuid();
+
+        let user = match &options.user {
+            Some(u) if u.uid != uid && !uid.is_root() => {
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    "only root may list the permissions of other users",
+                )
+                .into())
+            }
+            Some(u) => u.clone(),
+            None => match User::from_uid(uid)? {
+                Some(u) => u,
+                None => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::NotFound,
+                        "could not find this user, for some reason",
+                    )
+                    .into())
+                }
+            },
+        };
+
+        Self::new(
+            config,
+            &user,
+            &utils::get_host_name()?,
+            &Clock::system(),
+            &Origin::current(),
+        )
+    }
+
+    /// Check if any policy applies to the user.
+    #[must_use]
+    pub fn is_permitted(&self) -> bool {
+        self.permits.is_some()
+    }
+}
+
+/// Join a list of values, or show `empty` if there are none.
+fn join<T: fmt::Display>(values: &[T], empty: &str) -> String {
+    if values.is_empty() {
+        return empty.to_owned();
+    }
+
+    values
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl fmt::Display for Listing<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (permits, session) = match (&self.permits, &self.session) {
+            (Some(p), Some(s)) => (p, s),
+            _ => {
+                write!(f, "No policy applies to {} on {}", self.user, self.host)?;
+                if let Some(r) = &self.reason {
+                    write!(f, ": {}", r)?;
+                }
+                return writeln!(f);
+            }
+        };
+
+        writeln!(f, "Permissions of {} on {}:", self.user, self.host)?;
+
+        if self.root {
+            writeln!(f, "    mapping:       (root; configurations are ignored)")?;
+        }
+        for m in &self.mappings {
+            let kind = match m.kind {
+                MappingKind::User => "user",
+                MappingKind::Group => "group",
+            };
+            writeln!(
+                f,
+                "    mapping:       {} {} (key \"{}\") -> policy \"{}\"",
+                kind, m.name, m.key, m.policy
+            )?;
+        }
+
+        // Name the policy of each condition if policies were merged
+        let merged = self.conditions.len() > 1;
+        for c in &self.conditions {
+            let of = if merged {
+                format!(" (policy \"{}\")", c.policy)
+            } else {
+                String::new()
+            };
+
+            if let Some(hosts) = c.hosts {
+                writeln!(f, "    hosts:         {}{}", join(hosts, "(none)"), of)?;
+            }
+            if let Some(d) = &c.valid_from {
+                writeln!(f, "    valid-from:    {}{}", d, of)?;
+            }
+            if let Some(d) = &c.valid_until {
+                writeln!(f, "    valid-until:   {}{}", d, of)?;
+            }
+            if !c.windows.is_empty() {
+                writeln!(f, "    windows:       {}{}", join(c.windows, ""), of)?;
+            }
+            if let Some(origin) = c.origin {
+                if origin.require_tty {
+                    writeln!(f, "    require-tty:   true{}", of)?;
+                }
+                if let Some(ttys) = &origin.ttys {
+                    writeln!(f, "    ttys:          {}{}", join(ttys, "(none)"), of)?;
+                }
+                if !origin.allow_ssh {
+                    writeln!(f, "    allow-ssh:     false{}", of)?;
+                }
+            }
+        }
+
+        // Merged permits only permit commands together with targets of the same policy, which
+        // also sets their environment and process attributes
+        let merged = !permits.merged.is_empty();
+        for (i, permits) in permits.sources().enumerate() {
+            if let (true, Some(m)) = (merged, self.mappings.get(i)) {
+                writeln!(f, "    permits of:    policy \"{}\"", m.policy)?;
+            }
+
+            if permits.all_targets {
+                writeln!(f, "    targets:       (all)")?;
+            } else {
+                writeln!(f, "    targets:       {}", join(&permits.targets, "(none)"))?;
+            }
+            if !permits.deny_targets.is_empty() {
+                writeln!(f, "    deny-targets:  {}", join(&permits.deny_targets, ""))?;
+            }
+
+            let commands = permits
+                .commands
+                .as_deref()
+                .map_or_else(|| "(all)".to_owned(), |c| join(c, "(none)"));
+            writeln!(f, "    commands:      {}", commands)?;
+            if !permits.deny_commands.is_empty() {
+                writeln!(f, "    deny-commands: {}", join(&permits.deny_commands, ""))?;
+            }
+
+            if let Some(env) = self.env.get(i) {
+                writeln!(f, "    keep-env:      {}", join(&env.keep, "(none)"))?;
+                if !env.deny.is_empty() {
+                    writeln!(f, "    deny-env:      {}", join(&env.deny, ""))?;
+                }
+                if !env.set.is_empty() {
+                    let set: Vec<_> = env
+                        .set
+                        .iter()
+                        .map(|(k, v)| format!("{}={}", k, v))
+                        .collect();
+                    writeln!(f, "    set-env:       {}", join(&set, ""))?;
+                }
+                if let Some(file) = &env.file {
+                    writeln!(f, "    env-file:      {}", file.display())?;
+                }
+            }
+
+            if let Some(process) = self.process.get(i) {
+                if let Some(umask) = process.umask {
+                    writeln!(f, "    umask:         {:04o}", umask)?;
+                }
+                let limits: Vec<_> = process
+                    .limits
+                    .iter()
+                    .map(|(name, limit)| format!("{}={}", name, limit))
+                    .collect();
+                if !limits.is_empty() {
+                    writeln!(f, "    limits:        {}", join(&limits, ""))?;
+                }
+                if let Some(nice) = process.nice {
+                    writeln!(f, "    nice:          {}", nice)?;
+                }
+                if let Some(class) = process.ionice {
+                    match process.ionice_level {
+                        Some(level) => writeln!(f, "    ionice:        {} ({})", class, level)?,
+                        None => writeln!(f, "    ionice:        {}", class)?,
+                    }
+                }
+                if process.noexec {
+                    writeln!(f, "    noexec:        true")?;
+                }
+                if !process.capabilities.is_empty() {
+                    writeln!(f, "    capabilities:  {}", join(&process.capabilities, ""))?;
+                }
+                if let Some(root) = &process.chroot {
+                    writeln!(f, "    chroot:        {}", root.display())?;
+                }
+                if let Some(dir) = &process.chdir {
+                    writeln!(f, "    chdir:         {}", dir.display())?;
+                }
+                if !process.allow_chdir.is_empty() {
+                    writeln!(f, "    allow-chdir:   {}", join(&process.allow_chdir, ""))?;
+                }
+            }
+        }
+
+        writeln!(f, "    no-auth:       {}", session.no_auth)?;
+        if !session.no_auth {
+            match session.refresh {
+                Some(r) => writeln!(f, "    refresh:       {} minute(s)", r.as_secs() / 60)?,
+                None => writeln!(f, "    refresh:       (authenticate every time)")?,
+            }
+            if !session.no_auth_commands.is_empty() {
+                writeln!(
+                    f,
+                    "    no-auth for:   {}",
+                    join(&session.no_auth_commands, "")
+                )?;
+            }
+        }
+        if !session.always_auth_commands.is_empty() {
+            writeln!(
+                f,
+                "    always-auth:   {}",
+                join(&session.always_auth_commands, "")
+            )?;
+        }
+        writeln!(f, "    service:       {:?}", self.service)?;
+        if let Some(name) = &self.pam_service {
+            writeln!(f, "    pam-service:   {}", name)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Configuration mapping alice to the `ops` policy, and the `www` group to the `web` policy.
+    fn config(resolution: &str) -> Config {
+        toml::from_str(&format!(
+            "resolution = '{}'\nservice = 'Pam'\npam-service = 'mk-common'\n\
+             [policies.ops]\nhosts = ['web-*']\nvalid-until = 2030-01-01T00:00:00Z\n\
+             windows = [{{ days = ['thu'] }}]\n\
+             [policies.ops.permits]\ntargets = ['root', '%www']\n\
+             commands = ['/usr/bin/systemctl']\ndeny-commands = ['/bin/sh']\n\
+             [policies.ops.env]\nkeep = ['EDITOR']\nset = {{ PATH = '/usr/bin' }}\n\
+             [policies.ops.process]\numask = 0o027\nchroot = '/srv/ops'\n\
+             [policies.ops.session]\nrefresh = 5\n\
+             [policies.ops.auth]\npam-service = 'mk-ops'\n\
+             [policies.web.permits]\ntargets = ['www-data']\n\
+             [policies.weekend]\nwindows = [{{ days = ['sat', 'sun'] }}]\n\
+             [users]\nalice = 'ops'\nbob = 'weekend'\n[groups]\nwww = 'web'",
+            resolution
+        ))
+        .unwrap()
+    }
+
+    /// Lines of a listing, without indentation.
+    fn lines(listing: &Listing<'_>) -> Vec<String> {
+        listing
+            .to_string()
+            .lines()
+            .map(|l| l.trim_start().to_owned())
+            .collect()
+    }
+
+    /// List the permissions of a user on host `web-1` on a Thursday, at the Unix epoch.
+    fn list<'a>(config: &'a Config, user: Subject, groups: &[Subject]) -> Listing<'a> {
+        Listing::with_groups(
+            config,
+            &user,
+            groups,
+            "web-1",
+            &Clock::new(tz::TimeZone::utc(), 0),
+            &Origin::default(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_text() {
+        let config = config("priority");
+        let listing = list(&config, Subject::new("alice", 1000), &[]);
+
+        assert!(listing.is_permitted());
+        assert_eq!(
+            lines(&listing),
+            [
+                "Permissions of alice on web-1:",
+                "mapping:       user alice (key \"alice\") -> policy \"ops\"",
+                "hosts:         web-*",
+                "valid-until:   2030-01-01T00:00:00Z",
+                "windows:       thu",
+                "targets:       root, %www",
+                "commands:      /usr/bin/systemctl",
+                "deny-commands: /bin/sh",
+                "keep-env:      EDITOR",
+                "set-env:       PATH=/usr/bin",
+                "umask:         0027",
+                "chroot:        /srv/ops",
+                "no-auth:       false",
+                "refresh:       5 minute(s)",
+                "service:       Pam",
+                "pam-service:   mk-ops",
+            ]
+        );
+
+        let root = lines(&list(&config, Subject::new("root", 0), &[]));
+        assert!(root
+            .iter()
+            .any(|l| l == "mapping:       (root; configurations are ignored)"));
+        assert!(root.iter().any(|l| l == "targets:       (all)"));
+        assert!(root.iter().any(|l| l == "keep-env:      *"));
+        assert!(root.iter().any(|l| l == "no-auth:       true"));
+    }
+
+    #[test]
+    fn test_merged() {
+        let config = config("merge");
+        let listing = list(
+            &config,
+            Subject::new("alice", 1000),
+            &[Subject::new("www", 33)],
+        );
+
+        // Conditions, environment and process attributes are named by their policy
+        let text = lines(&listing);
+        assert!(text
+            .iter()
+            .any(|l| l == "windows:       thu (policy \"ops\")"));
+        let web = text
+            .iter()
+            .position(|l| l == "permits of:    policy \"web\"")
+            .unwrap();
+        assert_eq!(
+            text[web + 1..web + 4],
+            [
+                "targets:       www-data",
+                "commands:      (all)",
+                "keep-env:      (none)"
+            ]
+        );
+
+        let json: serde_json::Value = serde_json::to_value(&listing).unwrap();
+        assert_eq!(json["mappings"][1]["policy"], "web");
+        assert_eq!(json["conditions"][0]["windows"][0]["days"][0], "thu");
+        assert_eq!(
+            json["conditions"][1],
+            serde_json::json!({ "policy": "web" })
+        );
+        assert_eq!(json["permits"]["merged"][0]["targets"][0], "www-data");
+        assert_eq!(json["env"][0]["keep"][0], "EDITOR");
+        assert_eq!(json["env"][1], serde_json::json!({}));
+        assert_eq!(json["process"][0]["chroot"], "/srv/ops");
+        assert_eq!(json["process"][1].get("chroot"), None);
+        assert_eq!(json["pam-service"], "mk-ops");
+    }
+
+    #[test]
+    fn test_no_policy() {
+        let config = config("priority");
+
+        let listing = list(&config, Subject::new("carol", 1002), &[]);
+        assert!(!listing.is_permitted());
+        assert_eq!(listing.to_string(), "No policy applies to carol on web-1\n");
+
+        // Policies that don't apply at this time are named
+        let listing = list(&config, Subject::new("bob", 1001), &[]);
+        assert_eq!(
+            listing.to_string(),
+            "No policy applies to bob on web-1: policy weekend is outside of its permitted time \
+             windows\n"
+        );
+
+        let json = serde_json::to_value(&listing).unwrap();
+        assert_eq!(json["permits"], serde_json::Value::Null);
+        assert_eq!(
+            json["reason"],
+            "policy weekend is outside of its permitted time windows"
+        );
+    }
+}
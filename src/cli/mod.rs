@@ -1,31 +1,137 @@
 //! Command line tools for `mk`.
 
+use std::io;
 use std::process::exit;
 
+use nix::unistd;
+
 use crate::config::Config;
+use crate::diagnostics::{Diagnostic, Severity};
+use crate::import;
+use crate::options::{CheckOptions, ImportFormat, ImportOptions, ListOptions, MkOptions};
 use crate::prelude::*;
 
 mod app;
+mod list;
 mod options;
 
 pub use app::App;
+pub use list::{Conditions, Listing};
 
 fn exit_with_err(err: &Error) -> ! {
     eprintln!("{}: {}", SERVICE_NAME, err);
     exit(-1);
 }
 
+/// Drop all privileges, so that files are never read with more privileges than the invoking user
+/// has.
+fn drop_privileges() {
+    if let Err(e) = unistd::setgid(unistd::getgid()).and_then(|_| unistd::setuid(unistd::getuid()))
+    {
+        exit_with_err(&e.into());
+    }
+}
+
+/// Print diagnostics along with a summary, and exit with a non-zero status if any are errors.
+fn exit_with_diagnostics(diagnostics: &[Diagnostic]) -> ! {
+    for d in diagnostics {
+        eprintln!("{}", d);
+    }
+
+    let errors = diagnostics
+        .iter()
+        .filter(|d| d.severity == Severity::Error)
+        .count();
+
+    if !diagnostics.is_empty() {
+        eprintln!(
+            "{}: {} error(s), {} warning(s)",
+            SERVICE_NAME,
+            errors,
+            diagnostics.len() - errors
+        );
+    }
+
+    exit(if errors > 0 { 1 } else { 0 });
+}
+
+/// Check configurations.
+fn check_config(options: &CheckOptions) -> ! {
+    drop_privileges();
+
+    let diagnostics = match &options.path {
+        Some(p) => Config::check_file(p),
+        None => Config::check_system(),
+    };
+
+    exit_with_diagnostics(&diagnostics);
+}
+
+/// Print the converted configurations of another tool.
+fn import_config(options: &ImportOptions) -> ! {
+    drop_privileges();
+
+    let contents = match std::fs::read_to_string(&options.path) {
+        Err(e) => exit_with_err(&e.into()),
+        Ok(c) => c,
+    };
+
+    let conversion = match options.format {
+        ImportFormat::Doas => import::doas::convert(&contents, &options.path),
+        ImportFormat::Sudoers => import::sudoers::convert(&contents, &options.path),
+    };
+
+    // Errors may leave out rules the output would need to be as strict as the original
+    if !conversion
+        .diagnostics
+        .iter()
+        .any(|d| d.severity == Severity::Error)
+    {
+        print!("{}", conversion.toml);
+    }
+    exit_with_diagnostics(&conversion.diagnostics);
+}
+
+/// Print the permissions of a user, and exit with a non-zero status if no policy applies to them.
+fn list_permissions(config: &Config, options: &ListOptions) -> ! {
+    let listing = match Listing::from_options(config, options) {
+        Err(e) => exit_with_err(&e),
+        Ok(l) => l,
+    };
+
+    if options.json {
+        match serde_json::to_string_pretty(&listing) {
+            Err(e) => exit_with_err(&io::Error::from(e).into()),
+            Ok(s) => println!("{}", s),
+        }
+    } else {
+        print!("{}", listing);
+    }
+
+    exit(if listing.is_permitted() { 0 } else { 1 });
+}
+
 pub fn run(args: Vec<String>) -> ! {
     let opts = match options::from_terminal(args) {
         Err(e) => exit_with_err(&e),
         Ok(i) => i,
     };
 
-    let conf = match Config::from_file("/etc/mk.conf") {
+    match &opts {
+        MkOptions::Check(o) => check_config(o),
+        MkOptions::Import(o) => import_config(o),
+        _ => {}
+    }
+
+    let conf = match Config::system() {
         Err(e) => exit_with_err(&e),
         Ok(i) => i,
     };
 
+    if let MkOptions::List(o) = &opts {
+        list_permissions(&conf, o);
+    }
+
     let mut app = match App::new(&conf) {
         Err(e) => exit_with_err(&e),
         Ok(i) => i,
@@ -6,6 +6,7 @@ use std::path::PathBuf;
 use clap::{App, AppSettings, Arg};
 use nix::unistd::User;
 
+use crate::import::{doas, sudoers};
 use crate::options::*;
 use crate::prelude::*;
 
@@ -29,7 +30,15 @@ pub fn from_terminal(args: Vec<String>) -> Result<MkOptions> {
                 .short('E')
                 .long("preserve-env")
                 .takes_value(true)
-                .about("Preserve existing environment variables"),
+                .about("Keep the given comma-separated environment variables, if permitted"),
+        )
+        .arg(
+            Arg::new("chdir")
+                .short('D')
+                .long("chdir")
+                .value_name("DIR")
+                .takes_value(true)
+                .about("Run the command in the given directory, if permitted"),
         )
         .arg(
             Arg::new("edit")
@@ -37,6 +46,60 @@ pub fn from_terminal(args: Vec<String>) -> Result<MkOptions> {
                 .long("edit")
                 .takes_value(true)
                 .about("Edit a file as the target user"),
+        )
+        .arg(
+            Arg::new("explain")
+                .long("explain")
+                .about("Explain whether the command would be permitted, without running it"),
+        )
+        .arg(
+            Arg::new("list")
+                .short('l')
+                .long("list")
+                .about("List the permissions of the invoking user and exit"),
+        )
+        .arg(
+            Arg::new("other-user")
+                .short('U')
+                .long("other-user")
+                .takes_value(true)
+                .requires("list")
+                .about("List the permissions of another user (root only)"),
+        )
+        .arg(
+            Arg::new("json")
+                .long("json")
+                .requires("list")
+                .about("List permissions as JSON"),
+        )
+        .arg(
+            Arg::new("check-config")
+                .long("check-config")
+                .value_name("FILE")
+                .takes_value(true)
+                .min_values(0)
+                .max_values(1)
+                .about("Check a configuration file, or the system configurations, and exit"),
+        )
+        .arg(
+            Arg::new("import-doas")
+                .long("import-doas")
+                .value_name("FILE")
+                .takes_value(true)
+                .min_values(0)
+                .max_values(1)
+                .about(
+                    "Print the equivalent of a doas.conf file (default: /etc/doas.conf) and exit",
+                ),
+        )
+        .arg(
+            Arg::new("import-sudoers")
+                .long("import-sudoers")
+                .value_name("FILE")
+                .takes_value(true)
+                .min_values(0)
+                .max_values(1)
+                .about("Print the equivalent of a sudoers file (default: /etc/sudoers) and exit"),
         );
 
     let usage = app.generate_usage();
@@ -51,22 +114,42 @@ pub fn from_terminal(args: Vec<String>) -> Result<MkOptions> {
         }
     };
 
-    let target = {
-        let target_str = match matches.value_of("user") {
-            Some(u) => u,
-            _ => "root",
-        };
+    if matches.is_present("check-config") {
+        return Ok(MkOptions::Check(CheckOptions {
+            path: matches.value_of("check-config").map(PathBuf::from),
+        }));
+    }
+
+    if matches.is_present("import-doas") {
+        return Ok(MkOptions::Import(ImportOptions {
+            format: ImportFormat::Doas,
+            path: PathBuf::from(matches.value_of("import-doas").unwrap_or(doas::PATH)),
+        }));
+    }
+
+    if matches.is_present("import-sudoers") {
+        return Ok(MkOptions::Import(ImportOptions {
+            format: ImportFormat::Sudoers,
+            path: PathBuf::from(matches.value_of("import-sudoers").unwrap_or(sudoers::PATH)),
+        }));
+    }
 
-        match User::from_name(target_str)? {
-            Some(u) => u,
-            _ => {
-                return Err(
-                    Error::new(ErrorKind::NotFound, format!("unknown user {}", target_str)).into(),
-                )
-            }
+    let find_user = |name: &str| -> Result<User> {
+        match User::from_name(name)? {
+            Some(u) => Ok(u),
+            _ => Err(Error::new(ErrorKind::NotFound, format!("unknown user {}", name)).into()),
         }
     };
 
+    if matches.is_present("list") {
+        return Ok(MkOptions::List(ListOptions {
+            user: matches.value_of("other-user").map(find_user).transpose()?,
+            json: matches.is_present("json"),
+        }));
+    }
+
+    let target = find_user(matches.value_of("user").unwrap_or("root"))?;
+
     // Parse edit options
     if let Some(e) = matches.value_of("edit") {
         return Ok(MkOptions::Edit(EditOptions {
@@ -82,14 +165,21 @@ pub fn from_terminal(args: Vec<String>) -> Result<MkOptions> {
             _ => Vec::new(),
         };
 
-        return Ok(MkOptions::Command(CommandOptions {
+        let options = CommandOptions {
             target,
             command: ext_cmd.to_string(),
             args,
             preserve_env: matches
                 .value_of("preserve-env")
                 .map(|s| s.split(',').map(std::borrow::ToOwned::to_owned).collect()),
-        }));
+            chdir: matches.value_of("chdir").map(PathBuf::from),
+        };
+
+        if matches.is_present("explain") {
+            return Ok(MkOptions::Explain(options));
+        }
+
+        return Ok(MkOptions::Command(options));
     }
 
     Ok(MkOptions::Text(usage))
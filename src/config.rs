@@ -1,16 +1,73 @@
 //! `mk` configurations.
 
+use std::borrow::Cow;
 use std::collections::HashMap;
-use std::ffi::CString;
 use std::fs;
 use std::io;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-use nix::unistd;
+use indexmap::IndexMap;
+use nix::unistd::{self, Group, User};
 
-use crate::auth::AuthService;
+use crate::auth::{self, AuthService};
+use crate::decision::{Decision, Request, Rule, TargetRule, Verdict};
+use crate::diagnostics::{Diagnostic, Severity};
+use crate::mapping::{Key, Mapping, Subject};
+use crate::origin::Origin;
+use crate::permits::{matches_target, Permits, Target};
 use crate::policy::Policy;
 use crate::prelude::*;
+use crate::schedule::{self, Clock};
+use crate::session::AuthRequirement;
+
+/// Ways to pick a policy when several mappings apply to a user.
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Resolution {
+    /// Use the policy of the mapping with the highest precedence.
+    Priority,
+    /// Merge the policies of all mappings. See [`Policy::merge`].
+    Merge,
+}
+
+impl Default for Resolution {
+    fn default() -> Self {
+        Self::Priority
+    }
+}
+
+/// Whether a mapping applies to a user, or to one of their groups.
+///
+/// User mappings are ordered before group mappings.
+#[derive(Debug, serde::Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum MappingKind {
+    User,
+    Group,
+}
+
+/// A mapping that applied to a user.
+#[derive(Debug, serde::Serialize, Clone, PartialEq, Eq)]
+pub struct MatchedMapping {
+    /// Whether this is a user or a group mapping.
+    pub kind: MappingKind,
+    /// Key of the mapping.
+    pub key: String,
+    /// Name of the user or group the key matched.
+    pub name: String,
+    /// Name of the mapped policy.
+    pub policy: String,
+}
+
+/// The policy that applies to a user, along with the mappings it was resolved from.
+#[derive(Debug, Clone)]
+pub struct ResolvedPolicy<'a> {
+    /// The resolved policy.
+    pub policy: Cow<'a, Policy>,
+    /// Mappings whose policies were used, in order of precedence. There is more than one only if
+    /// policies are merged (see [`Resolution::Merge`]).
+    pub mappings: Vec<MatchedMapping>,
+}
 
 /// Global `mk` configurations.
 #[readonly::make]
@@ -19,52 +76,2012 @@ pub struct Config {
     /// All defined policies.
     #[serde(default = "HashMap::new")]
     pub policies: HashMap<String, Policy>,
-    /// User policies. Values correspond to a predefined policy.
-    #[serde(default = "HashMap::new")]
-    pub users: HashMap<String, String>,
-    /// Group policies. Values correspond to a predefined policy.
-    #[serde(default = "HashMap::new")]
-    pub groups: HashMap<String, String>,
-    /// Default authentication service to use.
+    /// User policies. Keys are user names, UIDs or UID ranges (see [`Key`]). Values correspond to
+    /// a predefined policy.
+    #[serde(default = "IndexMap::new")]
+    pub users: IndexMap<String, Mapping>,
+    /// Group policies, in the order they were defined. Keys are group names, GIDs or GID ranges
+    /// (see [`Key`]). Values correspond to a predefined policy.
+    #[serde(default = "IndexMap::new")]
+    pub groups: IndexMap<String, Mapping>,
+    /// Default authentication service to use. This may only be set in one file.
     #[serde(default = "AuthService::default")]
     pub service: AuthService,
+    /// Name of the PAM service to use, unless a policy names another. This may only be set in one
+    /// file. Defaults to `mk`.
+    #[serde(rename = "pam-service")]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pam_service: Option<String>,
+    /// How to pick a policy when several mappings apply to a user. This may only be set in one
+    /// file.
+    #[serde(default = "Resolution::default")]
+    pub resolution: Resolution,
+    /// Additional configuration files to read. Relative paths are resolved from the directory
+    /// of the including file.
+    #[serde(default = "Vec::new")]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<PathBuf>,
+    /// Directories from which to read additional configuration files. All `*.toml` files in each
+    /// directory are read in lexical order. Relative paths are resolved from the directory of the
+    /// including file.
+    #[serde(rename = "include-dir")]
+    #[serde(default = "Vec::new")]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub include_dir: Vec<PathBuf>,
 }
 
 impl Config {
-    /// Try to read configurations from a file.
+    /// Path to the system configuration file.
+    pub const PATH: &'static str = "/etc/mk.conf";
+
+    /// Directory from which system configuration fragments are read.
+    pub const DROP_IN_DIR: &'static str = "/etc/mk.d";
+
+    /// Try to read the system configurations from [`Config::PATH`], followed by all fragments in
+    /// [`Config::DROP_IN_DIR`] if it exists.
+    pub fn system() -> Result<Self> {
+        let mut loader = Loader::default();
+        let config = Self::load_system(&mut loader)?;
+        loader.finish(config)
+    }
+
+    /// Read the system configuration file and all fragments with a loader.
+    fn load_system(loader: &mut Loader) -> Result<Self> {
+        let mut config = loader.load(Path::new(Self::PATH))?;
+
+        let drop_in = Path::new(Self::DROP_IN_DIR);
+        if drop_in.is_dir() {
+            loader.load_dir(&mut config, drop_in)?;
+        }
+
+        Ok(config)
+    }
+
+    /// Get the authentication service used with a policy.
+    ///
+    /// This is the policy's own service if it names one, or [`Config::service`] otherwise.
+    #[must_use]
+    pub fn service_for(&self, policy: &Policy) -> AuthService {
+        policy.auth.service.unwrap_or(self.service)
+    }
+
+    /// Get the authenticator rules of a policy, with the global PAM service filled in if the
+    /// policy names none.
+    #[must_use]
+    pub fn auth_rules_for(&self, policy: &Policy) -> auth::Rules {
+        policy.auth.or_pam_service(self.pam_service.as_deref())
+    }
+
+    /// Try to read configurations from a file, along with all files it includes.
+    ///
+    /// # Errors
+    ///
+    /// This fails if any file could not be read or parsed, if any file or its parent directories
+    /// could be modified by users other than root (see [`utils::check_secure_path`]), if a file is
+    /// included more than once, if a policy or mapping is defined in more than one file, if any
+    /// key is unknown, or if a policy extends an undefined policy or itself.
     #[inline]
     pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
-        toml::from_str(&fs::read_to_string(path)?[..])
-            .map_err(|e| io::Error::new(io::ErrorKind::Other, e).into())
+        let mut loader = Loader::default();
+        let config = loader.load(path.as_ref())?;
+        loader.finish(config)
     }
 
-    #[must_use]
+    /// Try to read configurations from a file like [`Config::from_file`], without checking who
+    /// could have modified the files.
+    ///
+    /// Configurations read this way must never be used to grant privileges.
+    pub fn from_file_unchecked<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let mut loader = Loader {
+            unchecked: true,
+            ..Loader::default()
+        };
+        let config = loader.load(path.as_ref())?;
+        loader.finish(config)
+    }
+
+    /// Check the system configurations, as read by [`Config::system`], and report all problems
+    /// found.
+    ///
+    /// See [`Config::check_file`].
+    pub fn check_system() -> Vec<Diagnostic> {
+        Loader::checking().check(Path::new(Self::PATH), Self::load_system)
+    }
+
+    /// Check a configuration file and all files it includes, and report all problems found.
+    ///
+    /// Besides anything that would prevent the configurations from being read, such as unknown
+    /// keys, this reports mappings to undefined policies, insecure files and authentication
+    /// services that are not available as errors. Mappings or targets naming unknown users or
+    /// groups, and policies that are never used, are reported as warnings.
+    ///
+    /// Diagnostics are ordered by file and position.
+    pub fn check_file<P: AsRef<Path>>(path: P) -> Vec<Diagnostic> {
+        let path = path.as_ref();
+        Loader::checking().check(path, |loader| loader.load(path))
+    }
+
+    /// Get the policy a user is mapped to on this host, at the current time, from where this
+    /// process was invoked.
+    ///
+    /// See [`Config::find_user_policy`].
+    #[inline]
+    pub fn get_user_policy(&self, user: &unistd::User) -> Result<Option<Cow<'_, Policy>>> {
+        self.find_user_policy(
+            user,
+            &utils::get_host_name()?,
+            &Clock::system(),
+            &Origin::current(),
+        )
+    }
+
+    /// Get the policy a user is mapped to on a given host, at the time of a given clock, when
+    /// invoked from `origin`.
+    ///
+    /// See [`Config::resolve_user_policy`].
+    #[inline]
+    pub fn find_user_policy(
+        &self,
+        user: &unistd::User,
+        host: &str,
+        clock: &Clock,
+        origin: &Origin,
+    ) -> Result<Option<Cow<'_, Policy>>> {
+        Ok(self
+            .find_resolved_policy(user, host, clock, origin)?
+            .map(|r| r.policy))
+    }
+
+    /// Get the policy a user is mapped to on a given host, at the time of a given clock, when
+    /// invoked from `origin`, along with the mappings it was resolved from.
+    ///
+    /// See [`Config::resolve_user`].
+    pub fn find_resolved_policy(
+        &self,
+        user: &unistd::User,
+        host: &str,
+        clock: &Clock,
+        origin: &Origin,
+    ) -> Result<Option<ResolvedPolicy<'_>>> {
+        let groups: Vec<_> = utils::get_user_groups(user)?
+            .into_iter()
+            .map(Subject::from)
+            .collect();
+
+        let subject = Subject::from(user);
+        self.resolve_user(&subject, &groups, host, clock, origin)
+    }
+
+    /// Get the policy for a user who is a member of `groups`, on a given host at the time of a
+    /// given clock, when invoked from `origin`.
+    ///
+    /// See [`Config::resolve_user`].
     #[inline]
-    pub fn get_user_policy(&self, user: &unistd::User) -> Result<Option<&Policy>> {
-        // Get user specific policy
-        if let Some(p) = self
-            .users
-            .get(&user.name)
-            .and_then(|s| self.policies.get(s))
-        {
-            return Ok(Some(p));
+    pub fn resolve_user_policy(
+        &self,
+        user: &Subject,
+        groups: &[Subject],
+        host: &str,
+        clock: &Clock,
+        origin: &Origin,
+    ) -> Result<Option<Cow<'_, Policy>>> {
+        Ok(self
+            .resolve_user(user, groups, host, clock, origin)?
+            .map(|r| r.policy))
+    }
+
+    /// Get the policy for a user who is a member of `groups`, on a given host at the time of a
+    /// given clock, when invoked from `origin`, along with the mappings it was resolved from.
+    ///
+    /// Mappings are ordered by their priority, highest first. Mappings of equal priority are
+    /// ordered with the user's own mappings first, followed by group mappings. Within each, names
+    /// are preferred over IDs, which are preferred over ID ranges, and are otherwise kept in the
+    /// order they were defined. Mappings or policies that are restricted to other hosts, and
+    /// policies that are not valid at this time or may not be used from `origin`, are skipped.
+    /// Depending on [`Config::resolution`], either the first remaining policy is used, or all
+    /// remaining policies are merged.
+    ///
+    /// # Errors
+    ///
+    /// If no policy was found, and a policy was skipped because it was not valid at this time or
+    /// from this origin, an [`io::Error`] of kind [`io::ErrorKind::PermissionDenied`] describing
+    /// why is returned.
+    pub fn resolve_user(
+        &self,
+        user: &Subject,
+        groups: &[Subject],
+        host: &str,
+        clock: &Clock,
+        origin: &Origin,
+    ) -> Result<Option<ResolvedPolicy<'_>>> {
+        self.trace_user(user, groups, host, clock, origin, &mut Vec::new())
+    }
+
+    /// Resolve the policy of a user like [`Config::resolve_user`], recording each step taken in
+    /// `trace`.
+    fn trace_user(
+        &self,
+        user: &Subject,
+        groups: &[Subject],
+        host: &str,
+        clock: &Clock,
+        origin: &Origin,
+        trace: &mut Vec<String>,
+    ) -> Result<Option<ResolvedPolicy<'_>>> {
+        let describe = |kind: &str, subject: &Subject, mappings: &IndexMap<String, Mapping>| {
+            let keys: Vec<_> = mappings
+                .iter()
+                .filter(|(k, _)| Key::parse(k).map_or(false, |k| k.matches(subject)))
+                .map(|(k, m)| format!("`{}` -> policy {}", k, m.policy))
+                .collect();
+
+            format!(
+                "{} {} (#{}): {}",
+                kind,
+                subject.name,
+                subject.id,
+                if keys.is_empty() {
+                    "no mapping".to_owned()
+                } else {
+                    keys.join(", ")
+                }
+            )
+        };
+
+        trace.push(describe("user", user, &self.users));
+        for g in groups {
+            trace.push(describe("group", g, &self.groups));
+        }
+
+        let mut candidates = matching_mappings(&self.users, MappingKind::User, &[user]);
+        candidates.extend(matching_mappings(
+            &self.groups,
+            MappingKind::Group,
+            &groups.iter().collect::<Vec<_>>(),
+        ));
+        // Stable, so definition order is kept otherwise
+        candidates.sort_by_key(|(specificity, matched, m)| {
+            (std::cmp::Reverse(m.priority), matched.kind, *specificity)
+        });
+
+        let mut inactive = None;
+        let mut resolved: Option<ResolvedPolicy<'_>> = None;
+
+        for (_, matched, m) in candidates {
+            let candidate = format!(
+                "{} mapping `{}` -> policy {} (priority {})",
+                match matched.kind {
+                    MappingKind::User => "user",
+                    MappingKind::Group => "group",
+                },
+                matched.key,
+                m.policy,
+                m.priority
+            );
+
+            let policy = match self.resolve(m, host, clock, origin) {
+                Ok(p) => p,
+                Err(Skipped::Elsewhere(reason)) => {
+                    trace.push(format!("{}: skipped, {}", candidate, reason));
+                    continue;
+                }
+                Err(Skipped::Inactive(reason)) => {
+                    trace.push(format!("{}: skipped, {}", candidate, reason));
+                    inactive.get_or_insert(reason);
+                    continue;
+                }
+            };
+
+            resolved = match (self.resolution, resolved) {
+                (_, None) => {
+                    trace.push(format!("{}: used", candidate));
+                    Some(ResolvedPolicy {
+                        policy: Cow::Borrowed(policy),
+                        mappings: vec![matched],
+                    })
+                }
+                (Resolution::Merge, Some(mut r)) => {
+                    trace.push(format!("{}: merged", candidate));
+                    r.policy = Cow::Owned(Policy::merge(&r.policy, policy));
+                    r.mappings.push(matched);
+                    Some(r)
+                }
+                (Resolution::Priority, Some(r)) => Some(r),
+            };
+
+            if self.resolution == Resolution::Priority {
+                break;
+            }
+        }
+
+        match (resolved, inactive) {
+            (None, Some(e)) => {
+                trace.push(format!("no policy applies: {}", e));
+                Err(io::Error::new(io::ErrorKind::PermissionDenied, e).into())
+            }
+            (None, None) => {
+                trace.push("no policy applies".into());
+                Ok(None)
+            }
+            (r, _) => Ok(r),
+        }
+    }
+
+    /// Decide whether a request is allowed.
+    ///
+    /// Root is permitted everything without authentication, and all configurations are ignored.
+    /// Otherwise, the invoker's policy is resolved as in [`Config::resolve_user`]. Deny rules are
+    /// checked first, followed by permitted targets and commands. The invoker may always run
+    /// commands as themselves, unless a deny rule matches.
+    ///
+    /// This never queries the system, so everything about the invoker and target must be part of
+    /// the request.
+    #[inline]
+    pub fn evaluate(&self, request: &Request) -> Decision<'_> {
+        self.explain(request, &mut Vec::new())
+    }
+
+    /// Decide whether a request is allowed like [`Config::evaluate`], recording a description of
+    /// each step taken in `trace`.
+    ///
+    /// Every mapping that applies to the invoker and their groups, each candidate policy in order
+    /// of precedence, each permit rule checked until one decides the request, and whether
+    /// authentication is required are recorded.
+    pub fn explain(&self, request: &Request, trace: &mut Vec<String>) -> Decision<'_> {
+        let deny = |trace: &mut Vec<String>, policy, rule: Rule| {
+            trace.push(format!("denied: {}", rule));
+            Decision {
+                verdict: Verdict::Deny,
+                policy,
+                rule,
+                auth_required: false,
+            }
+        };
+
+        if request.invoker.id == 0 {
+            trace.push("invoker is root: configurations are ignored".into());
+            trace.push("permitted: invoked by root, no authentication required".into());
+            return Decision {
+                verdict: Verdict::Allow,
+                policy: Some(ResolvedPolicy {
+                    policy: Cow::Owned(Policy::root()),
+                    mappings: Vec::new(),
+                }),
+                rule: Rule::Root,
+                auth_required: false,
+            };
+        }
+
+        let resolved = match self.trace_user(
+            request.invoker,
+            request.groups,
+            request.host,
+            request.clock,
+            request.origin,
+            trace,
+        ) {
+            Ok(Some(r)) => r,
+            Ok(None) => return deny(trace, None, Rule::NoPolicy(None)),
+            Err(e) => return deny(trace, None, Rule::NoPolicy(Some(e.to_string()))),
+        };
+
+        let (source, rule) =
+            match check_permits(&resolved.policy.permits, &resolved.mappings, request, trace) {
+                Ok(r) => r,
+                Err(r) => return deny(trace, Some(resolved), r),
+            };
+
+        // Only the policy that permitted the request decides how its command is run
+        let mut resolved = resolved;
+        if resolved.mappings.len() > 1 {
+            if let Some((name, p)) = resolved
+                .mappings
+                .get(source)
+                .and_then(|m| Some((&m.policy, self.policies.get(&m.policy)?)))
+            {
+                trace.push(format!("policy {}: env and process rules apply", name));
+                resolved.policy = Cow::Owned(resolved.policy.with_rules_of(p));
+            }
+        }
+
+        let session = &resolved.policy.session;
+        let service = self.service_for(&resolved.policy);
+        let (path, args) = (request.command, request.args);
+        let requirement = session.auth_for(path, args);
+        trace.push(match (requirement, session.refresh) {
+            (AuthRequirement::Never, _) => match session.no_auth_rule(path, args) {
+                Some(c) if !session.no_auth => format!(
+                    "session: no-auth-commands rule `{}`, no authentication required",
+                    c
+                ),
+                _ => "session: no-auth, no authentication required".into(),
+            },
+            (AuthRequirement::Refresh, Some(r)) => format!(
+                "session: authentication with {:?} required, unless done in the last {} minute(s)",
+                service,
+                r.as_secs() / 60
+            ),
+            _ => match session.always_auth_rule(path, args) {
+                Some(c) => format!(
+                    "session: always-auth-commands rule `{}`, authentication with {:?} required",
+                    c, service
+                ),
+                None => format!("session: authentication with {:?} required", service),
+            },
+        });
+        let auth_required = requirement != AuthRequirement::Never;
+        trace.push(format!("permitted: {}", rule));
+
+        Decision {
+            verdict: Verdict::Allow,
+            policy: Some(resolved),
+            rule,
+            auth_required,
+        }
+    }
+
+    /// Get the policy a mapping refers to, if both apply on a host at the time of a clock, and the
+    /// policy may be used from an origin.
+    fn resolve(
+        &self,
+        mapping: &Mapping,
+        host: &str,
+        clock: &Clock,
+        origin: &Origin,
+    ) -> core::result::Result<&Policy, Skipped> {
+        if !mapping.applies_to_host(host) {
+            return Err(Skipped::Elsewhere(format!(
+                "mapping does not apply on host {}",
+                host
+            )));
+        }
+
+        let policy = match self.policies.get(&mapping.policy) {
+            Some(p) => p,
+            None => {
+                return Err(Skipped::Elsewhere(format!(
+                    "policy {} is not defined",
+                    mapping.policy
+                )))
+            }
+        };
+
+        if !policy.applies_to_host(host) {
+            return Err(Skipped::Elsewhere(format!(
+                "policy {} does not apply on host {}",
+                mapping.policy, host
+            )));
+        }
+
+        if let Err(e) = policy.check_schedule(clock) {
+            return Err(Skipped::Inactive(format!(
+                "policy {} is {}",
+                mapping.policy, e
+            )));
+        }
+
+        if let Err(e) = policy.check_origin(origin) {
+            return Err(Skipped::Inactive(format!(
+                "policy {} is {}",
+                mapping.policy, e
+            )));
+        }
+
+        Ok(policy)
+    }
+}
+
+/// Check the target and command of a request against permits, recording each rule checked in
+/// `trace`.
+///
+/// If the permits were combined from several policies, named by `mappings`, the deny rules of all
+/// of them apply, but the target and the command must be permitted by the same policy.
+///
+/// Returns the index of the policy that permitted the request among [`Permits::sources`] along
+/// with the [`Rule::Permitted`] rule, or the rule that denied it.
+fn check_permits(
+    permits: &Permits,
+    mappings: &[MatchedMapping],
+    request: &Request,
+    trace: &mut Vec<String>,
+) -> core::result::Result<(usize, Rule), Rule> {
+    let (target, groups) = (request.target, request.target_groups);
+    let sources: Vec<_> = permits.sources().collect();
+
+    for t in sources.iter().flat_map(|p| &p.deny_targets) {
+        if matches_target(t, target, groups) {
+            trace.push(format!("deny-targets `{}`: matches {}", t, target.name));
+            return Err(Rule::DeniedTarget(t.clone()));
+        }
+        trace.push(format!("deny-targets `{}`: no match", t));
+    }
+
+    // Name the policy that rules belong to, if there are several
+    let label = |i: usize, trace: &mut Vec<String>| {
+        if let (true, Some(m)) = (sources.len() > 1, mappings.get(i)) {
+            trace.push(format!("policy {}:", m.policy));
         }
+    };
+
+    // Permits of each policy that permits the target, along with the target rule
+    let mut permitted = Vec::new();
+    for (i, p) in sources.iter().enumerate() {
+        label(i, trace);
 
-        // https://docs.rs/nix/0.22.1/nix/unistd/fn.getgrouplist.html
-        #[cfg(not(target_os = "macos"))]
-        // Get policy for any supplementary groups this user might be in
-        for g in unistd::getgrouplist(CString::new(&user.name[..])?.as_c_str(), user.gid)? {
-            if let Some(grp) = unistd::Group::from_gid(g)? {
-                if let Some(p) = self
-                    .groups
-                    .get(&grp.name)
-                    .and_then(|s| self.policies.get(s))
-                {
-                    return Ok(Some(p));
+        if target == request.invoker {
+            trace.push(format!("target {} is the invoker", target.name));
+            permitted.push((i, p, TargetRule::Invoker));
+        } else if p.all_targets {
+            trace.push("all-targets: permitted".into());
+            permitted.push((i, p, TargetRule::All));
+        } else {
+            for t in &p.targets {
+                if matches_target(t, target, groups) {
+                    trace.push(format!("targets `{}`: matches {}", t, target.name));
+                    permitted.push((i, p, TargetRule::Rule(t.clone())));
+                    break;
                 }
+                trace.push(format!("targets `{}`: no match", t));
+            }
+        }
+    }
+
+    if permitted.is_empty() {
+        return Err(Rule::UnpermittedTarget);
+    }
+
+    let command = request.command.display();
+
+    for c in sources.iter().flat_map(|p| &p.deny_commands) {
+        if c.matches(request.command, request.args) {
+            trace.push(format!("deny-commands `{}`: matches {}", c, command));
+            return Err(Rule::DeniedCommand(c.clone()));
+        }
+        trace.push(format!("deny-commands `{}`: no match", c));
+    }
+
+    for (i, p, target_rule) in permitted {
+        label(i, trace);
+        let rules = match &p.commands {
+            None => {
+                trace.push("commands: all permitted".into());
+                return Ok((
+                    i,
+                    Rule::Permitted {
+                        target: target_rule,
+                        command: None,
+                    },
+                ));
+            }
+            Some(rules) => rules,
+        };
+
+        for c in rules {
+            if c.matches(request.command, request.args) {
+                trace.push(format!("commands `{}`: matches {}", c, command));
+                return Ok((
+                    i,
+                    Rule::Permitted {
+                        target: target_rule,
+                        command: Some(c.clone()),
+                    },
+                ));
             }
+            trace.push(format!("commands `{}`: no match", c));
+        }
+    }
+
+    Err(Rule::UnpermittedCommand)
+}
+
+/// Why a mapping was skipped when resolving a policy.
+enum Skipped {
+    /// The mapping or its policy does not apply on this host, or the policy is not defined.
+    Elsewhere(String),
+    /// The policy is not valid at this time, or may not be used from where `mk` was invoked.
+    Inactive(String),
+}
+
+/// Get all mappings whose keys match any of `subjects`, along with the specificity of the key and
+/// the subject it matched.
+fn matching_mappings<'a>(
+    mappings: &'a IndexMap<String, Mapping>,
+    kind: MappingKind,
+    subjects: &[&Subject],
+) -> Vec<(u8, MatchedMapping, &'a Mapping)> {
+    mappings
+        .iter()
+        .filter_map(|(k, m)| {
+            let key = Key::parse(k).ok()?;
+            let subject = subjects.iter().find(|s| key.matches(s))?;
+            let matched = MatchedMapping {
+                kind,
+                key: k.clone(),
+                name: subject.name.clone(),
+                policy: m.policy.clone(),
+            };
+            Some((key.specificity(), matched, m))
+        })
+        .collect()
+}
+
+/// Reads configuration files and the files they include.
+///
+/// Policies are collected as raw tables, and only deserialized once all files are read, so that
+/// they can extend policies defined in any file.
+#[derive(Default)]
+struct Loader {
+    /// Skip checking who could have modified the files read.
+    unchecked: bool,
+    /// Collect diagnostics for problems that don't prevent the files from being read.
+    checking: bool,
+    /// Canonical paths of all files read so far.
+    visited: Vec<PathBuf>,
+    /// Contents of all files read so far, used to locate diagnostics.
+    contents: HashMap<PathBuf, String>,
+    /// Values of settings that may only be set in one file, and the files they were set in.
+    settings: HashMap<&'static str, (toml::Value, PathBuf)>,
+    /// Raw policy tables, and the files they were defined in.
+    policies: HashMap<String, (toml::Value, PathBuf)>,
+    /// Files each user and group mapping was defined in.
+    mappings: HashMap<(&'static str, String), PathBuf>,
+    /// Problems found so far, if checking.
+    diagnostics: Vec<Diagnostic>,
+}
+
+/// Create a configuration error for a file.
+fn file_error<D: std::fmt::Display>(path: &Path, e: D) -> Error {
+    Diagnostic::new(Severity::Error, path, None, e.to_string()).into()
+}
+
+/// Get the position of a TOML error, if known.
+fn error_position(e: &toml::de::Error) -> Option<(usize, usize)> {
+    e.line_col().map(|(line, col)| (line + 1, col + 1))
+}
+
+/// Get the keys leading to a value ignored while deserializing.
+fn ignored_key(path: &serde_ignored::Path<'_>) -> Vec<String> {
+    use serde_ignored::Path;
+
+    match path {
+        Path::Root => Vec::new(),
+        Path::Seq { parent, index } => {
+            let mut key = ignored_key(parent);
+            key.push(index.to_string());
+            key
+        }
+        Path::Map { parent, key: k } => {
+            let mut key = ignored_key(parent);
+            key.push(k.clone());
+            key
         }
+        Path::Some { parent }
+        | Path::NewtypeStruct { parent }
+        | Path::NewtypeVariant { parent } => ignored_key(parent),
+    }
+}
+
+/// Check if a value contains a nested key, as returned by [`ignored_key`].
+fn contains_key(mut value: &toml::Value, key: &[String]) -> bool {
+    for k in key {
+        let next = match value {
+            toml::Value::Table(t) => t.get(k),
+            toml::Value::Array(a) => k.parse::<usize>().ok().and_then(|i| a.get(i)),
+            _ => None,
+        };
+
+        value = match next {
+            Some(v) => v,
+            None => return false,
+        };
+    }
+
+    true
+}
+
+/// Recursively merge the tables in `overlay` into `base`. All other values in `overlay` replace
+/// those in `base`.
+fn merge_values(base: &mut toml::Value, overlay: toml::Value) {
+    match (base, overlay) {
+        (toml::Value::Table(base), toml::Value::Table(overlay)) => {
+            for (k, v) in overlay {
+                match base.get_mut(&k) {
+                    Some(b) => merge_values(b, v),
+                    None => {
+                        base.insert(k, v);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+impl Loader {
+    /// Settings that may only be set in one file.
+    const SETTINGS: &'static [&'static str] = &["service", "pam-service", "resolution"];
+
+    /// Create a loader that collects diagnostics.
+    fn checking() -> Self {
+        Self {
+            checking: true,
+            ..Self::default()
+        }
+    }
+
+    /// Create a diagnostic for a key in a file that was read.
+    fn diagnostic<S: Into<String>>(
+        &self,
+        severity: Severity,
+        path: &Path,
+        key: &[&str],
+        message: S,
+    ) -> Diagnostic {
+        match self.contents.get(path) {
+            Some(c) => Diagnostic::at_key(severity, path, c, key, message),
+            None => Diagnostic::new(severity, path, None, message),
+        }
+    }
+
+    /// Create a configuration error for a key in a file that was read.
+    fn error<S: Into<String>>(&self, path: &Path, key: &[&str], message: S) -> Error {
+        self.diagnostic(Severity::Error, path, key, message).into()
+    }
+
+    /// Report keys ignored while deserializing a value from a file.
+    ///
+    /// `prefix` is the key of the value in the file. Unknown keys are rejected unless checking,
+    /// since a misspelled key would otherwise silently fall back to a default, such as permitting
+    /// all commands.
+    fn report_ignored(
+        &mut self,
+        path: &Path,
+        prefix: &[&str],
+        ignored: Vec<Vec<String>>,
+    ) -> Result<()> {
+        for key in ignored {
+            let mut full: Vec<&str> = prefix.to_vec();
+            full.extend(key.iter().map(String::as_str));
+
+            let d = self.diagnostic(
+                Severity::Error,
+                path,
+                &full,
+                format!("unknown key `{}`", full.join(".")),
+            );
+            if !self.checking {
+                return Err(d.into());
+            }
+            self.diagnostics.push(d);
+        }
+
+        Ok(())
+    }
+
+    /// Read a configuration file, merging in all files it includes.
+    fn load(&mut self, path: &Path) -> Result<Config> {
+        let normalized = utils::normalize_path(path);
+        let path = normalized.as_path();
+
+        if !self.unchecked {
+            if let Err(e) = utils::check_secure_path(path) {
+                let d = Diagnostic::new(
+                    Severity::Error,
+                    path,
+                    None,
+                    format!("refusing to read: {}", e),
+                );
+                // Keep going if checking, to find any other problems
+                if !self.checking || e.kind() == io::ErrorKind::NotFound {
+                    return Err(d.into());
+                }
+                self.diagnostics.push(d);
+            }
+        }
+
+        let canonical = fs::canonicalize(path).map_err(|e| file_error(path, e))?;
+        if self.visited.contains(&canonical) {
+            return Err(file_error(path, "file is included more than once"));
+        }
+        self.visited.push(canonical);
+
+        let contents = fs::read_to_string(path).map_err(|e| file_error(path, e))?;
+        let mut value: toml::Value = toml::from_str(&contents[..]).map_err(|e| {
+            Error::from(Diagnostic::new(
+                Severity::Error,
+                path,
+                error_position(&e),
+                e.to_string(),
+            ))
+        })?;
+        self.contents.insert(path.to_owned(), contents);
+
+        let table = match value.as_table_mut() {
+            Some(t) => t,
+            None => return Err(file_error(path, "expected a table")),
+        };
+
+        if let Some(policies) = table.remove("policies") {
+            let policies = match policies {
+                toml::Value::Table(t) => t,
+                _ => return Err(self.error(path, &["policies"], "`policies` must be a table")),
+            };
+
+            for (name, policy) in policies {
+                if self.policies.contains_key(&name) {
+                    return Err(self.error(
+                        path,
+                        &["policies", &name],
+                        format!("policy `{}` is already defined", name),
+                    ));
+                }
+                self.policies.insert(name, (policy, path.to_owned()));
+            }
+        }
+
+        for key in Self::SETTINGS {
+            if let Some(v) = table.get(*key) {
+                if let Some((_, p)) = self.settings.get(key) {
+                    return Err(self.error(
+                        path,
+                        &[key],
+                        format!("{} is already set in {}", key, p.display()),
+                    ));
+                }
+                self.settings.insert(key, (v.clone(), path.to_owned()));
+            }
+        }
+
+        let mut ignored = Vec::new();
+        let mut config: Config =
+            serde_ignored::deserialize(value, |p| ignored.push(ignored_key(&p))).map_err(|e| {
+                // Values lose their position, so parse the file again to locate the error
+                match toml::from_str::<Config>(&self.contents[path]) {
+                    Err(e) => {
+                        Diagnostic::new(Severity::Error, path, error_position(&e), e.to_string())
+                            .into()
+                    }
+                    Ok(_) => file_error(path, e),
+                }
+            })?;
+        self.report_ignored(path, &[], ignored)?;
+
+        for (kind, mappings) in [("users", &config.users), ("groups", &config.groups)] {
+            for key in mappings.keys() {
+                Key::parse(key).map_err(|e| self.error(path, &[kind, key], e))?;
+            }
+        }
+
+        let base = path.parent().unwrap_or_else(|| Path::new("/"));
+
+        for include in config.include.clone() {
+            let include = base.join(include);
+            let fragment = self.load(&include)?;
+            self.merge(&mut config, fragment, &include)?;
+        }
+
+        for dir in config.include_dir.clone() {
+            self.load_dir(&mut config, &base.join(dir))?;
+        }
+
+        for (kind, mappings) in [("users", &config.users), ("groups", &config.groups)] {
+            for key in mappings.keys() {
+                self.mappings
+                    .entry((kind, key.clone()))
+                    .or_insert_with(|| path.to_owned());
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Read all `*.toml` files in a directory in lexical order, merging them into `config`.
+    fn load_dir(&mut self, config: &mut Config, dir: &Path) -> Result<()> {
+        let mut paths = Vec::new();
+
+        for entry in fs::read_dir(dir).map_err(|e| file_error(dir, e))? {
+            let path = entry.map_err(|e| file_error(dir, e))?.path();
+            let hidden = path
+                .file_name()
+                .map_or(true, |n| n.to_string_lossy().starts_with('.'));
+
+            if !hidden && path.is_file() && path.extension().map_or(false, |e| e == "toml") {
+                paths.push(path);
+            }
+        }
+
+        paths.sort();
+
+        for path in paths {
+            let fragment = self.load(&path)?;
+            self.merge(config, fragment, &path)?;
+        }
+
+        Ok(())
+    }
+
+    /// Merge the mappings of a configuration read from `source` into another.
+    fn merge(&self, config: &mut Config, other: Config, source: &Path) -> Result<()> {
+        let source = utils::normalize_path(source);
+        let kinds = [
+            ("users", "user", &mut config.users, other.users),
+            ("groups", "group", &mut config.groups, other.groups),
+        ];
+
+        for (key, kind, mappings, others) in kinds {
+            for (name, mapping) in others {
+                if mappings.contains_key(&name) {
+                    return Err(self.error(
+                        &source,
+                        &[key, &name],
+                        format!("{} mapping `{}` is already defined", kind, name),
+                    ));
+                }
+                mappings.insert(name, mapping);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Get the raw table of a policy, merged over the tables of all policies it extends.
+    ///
+    /// `chain` holds the names of all policies that extend this one, to detect cycles.
+    fn resolve_policy(
+        &self,
+        name: &str,
+        resolved: &mut HashMap<String, toml::Value>,
+        chain: &mut Vec<String>,
+    ) -> Result<toml::Value> {
+        if let Some(v) = resolved.get(name) {
+            return Ok(v.clone());
+        }
+
+        let (value, path) = &self.policies[name];
+        let extends = ["policies", name, "extends"];
+
+        if chain.iter().any(|n| n == name) {
+            chain.push(name.to_owned());
+            return Err(self.error(
+                path,
+                &extends,
+                format!("policy inheritance cycle: {}", chain.join(" -> ")),
+            ));
+        }
+
+        let parent = match value.get("extends") {
+            None => None,
+            Some(toml::Value::String(p)) => Some(p.clone()),
+            Some(_) => {
+                return Err(self.error(
+                    path,
+                    &extends,
+                    format!("policy `{}`: `extends` must be a string", name),
+                ))
+            }
+        };
+
+        let value = match parent {
+            Some(p) => {
+                if !self.policies.contains_key(&p) {
+                    return Err(self.error(
+                        path,
+                        &extends,
+                        format!("policy `{}` extends undefined policy `{}`", name, p),
+                    ));
+                }
+
+                chain.push(name.to_owned());
+                let mut base = self.resolve_policy(&p, resolved, chain)?;
+                chain.pop();
+
+                merge_values(&mut base, value.clone());
+                base
+            }
+            None => value.clone(),
+        };
+
+        resolved.insert(name.to_owned(), value.clone());
+        Ok(value)
+    }
+
+    /// Get the value of a setting that may only be set in one file.
+    fn setting<T: serde::de::DeserializeOwned>(&self, key: &str) -> Result<Option<T>> {
+        match self.settings.get(key) {
+            Some((v, path)) => v
+                .clone()
+                .try_into()
+                .map(Some)
+                .map_err(|e| self.error(path, &[key], e.to_string())),
+            None => Ok(None),
+        }
+    }
+
+    /// Resolve all policies and apply settings that are tracked across all files.
+    fn finish(&mut self, mut config: Config) -> Result<Config> {
+        let mut resolved = HashMap::new();
+        let mut names: Vec<_> = self.policies.keys().cloned().collect();
+        names.sort();
+
+        for name in names {
+            let value = self.resolve_policy(&name, &mut resolved, &mut Vec::new())?;
+            let (raw, path) = &self.policies[&name];
+            let key = ["policies", name.as_str()];
+
+            let mut ignored = Vec::new();
+            let policy: Policy = serde_ignored::deserialize(value, |p| {
+                let key = ignored_key(&p);
+                // Only report keys in this policy, not those it inherited
+                if contains_key(raw, &key) {
+                    ignored.push(key);
+                }
+            })
+            .map_err(|e| self.error(path, &key, format!("policy `{}`: {}", name, e)))?;
+
+            policy
+                .permits
+                .validate()
+                .map_err(|e| self.error(path, &key, format!("policy `{}`: {}", name, e)))?;
+            policy.auth.validate().map_err(|e| {
+                self.error(
+                    path,
+                    &["policies", &name, "auth", "pam-service"],
+                    format!("policy `{}`: {}", name, e),
+                )
+            })?;
+            for (k, d) in [
+                ("valid-from", &policy.valid_from),
+                ("valid-until", &policy.valid_until),
+            ] {
+                if let Some(d) = d {
+                    schedule::validate(d).map_err(|e| {
+                        self.error(
+                            path,
+                            &["policies", &name, k],
+                            format!("policy `{}`: {}", name, e),
+                        )
+                    })?;
+                }
+            }
+            policy.process.validate().map_err(|e| {
+                self.error(
+                    path,
+                    &["policies", &name, "process"],
+                    format!("policy `{}`: {}", name, e),
+                )
+            })?;
+
+            let path = path.clone();
+            self.report_ignored(&path, &key, ignored)?;
+
+            if let Some(service) = policy.auth.service {
+                self.check_service(&path, &["policies", &name, "auth", "service"], service)?;
+            }
+
+            config.policies.insert(name.clone(), policy);
+        }
+
+        config.service = self.setting("service")?.unwrap_or_default();
+        config.pam_service = self.setting("pam-service")?;
+        config.resolution = self.setting("resolution")?.unwrap_or_default();
+
+        if let Some(s) = &config.pam_service {
+            auth::validate_pam_service(s)
+                .map_err(|e| self.error(&self.settings["pam-service"].1, &["pam-service"], e))?;
+        }
+
+        if let Some((_, path)) = self.settings.get("service") {
+            let path = path.clone();
+            self.check_service(&path, &["service"], config.service)?;
+        }
+
+        Ok(config)
+    }
+
+    /// Check that an authentication service set at a key of a file is available in this build.
+    fn check_service(&mut self, path: &Path, key: &[&str], service: AuthService) -> Result<()> {
+        if service.is_available() {
+            return Ok(());
+        }
+
+        let d = self.diagnostic(
+            Severity::Error,
+            path,
+            key,
+            format!(
+                "authentication service {:?} is not available in this build",
+                service
+            ),
+        );
+        if !self.checking {
+            return Err(d.into());
+        }
+
+        self.diagnostics.push(d);
+        Ok(())
+    }
+
+    /// Read configurations with `load` and report all problems found.
+    ///
+    /// Errors that aren't specific to a file are reported for `root`.
+    fn check<F>(mut self, root: &Path, load: F) -> Vec<Diagnostic>
+    where
+        F: FnOnce(&mut Self) -> Result<Config>,
+    {
+        match load(&mut self).and_then(|c| self.finish(c)) {
+            Ok(config) => self.lint(&config),
+            Err(Error::Config(d)) => self.diagnostics.push(d),
+            Err(e) => {
+                self.diagnostics
+                    .push(Diagnostic::new(Severity::Error, root, None, e.to_string()))
+            }
+        }
+
+        let mut diagnostics = self.diagnostics;
+        diagnostics.sort_by(|a, b| (&a.path, a.position).cmp(&(&b.path, b.position)));
+        diagnostics
+    }
+
+    /// Look for problems in configurations that were read successfully.
+    fn lint(&mut self, config: &Config) {
+        let mut diagnostics = Vec::new();
+
+        for (kind, mappings) in [("users", &config.users), ("groups", &config.groups)] {
+            for (name, mapping) in mappings {
+                let path = &self.mappings[&(kind, name.clone())];
+                let key = [kind, name.as_str()];
+
+                if !config.policies.contains_key(&mapping.policy) {
+                    diagnostics.push(self.diagnostic(
+                        Severity::Error,
+                        path,
+                        &key,
+                        format!("undefined policy `{}`", mapping.policy),
+                    ));
+                }
+
+                if let Ok(Key::Name(n)) = Key::parse(name) {
+                    let known = match kind {
+                        "users" => User::from_name(n).map(|u| u.is_some()),
+                        _ => Group::from_name(n).map(|g| g.is_some()),
+                    };
+                    if let Ok(false) = known {
+                        diagnostics.push(self.diagnostic(
+                            Severity::Warning,
+                            path,
+                            &key,
+                            format!("unknown {} `{}`", &kind[..kind.len() - 1], n),
+                        ));
+                    }
+                }
+            }
+        }
+
+        for (name, (raw, path)) in &self.policies {
+            let used = config.users.values().any(|m| &m.policy == name)
+                || config.groups.values().any(|m| &m.policy == name)
+                || config
+                    .policies
+                    .values()
+                    .any(|p| p.extends.as_ref() == Some(name));
+
+            if !used {
+                diagnostics.push(self.diagnostic(
+                    Severity::Warning,
+                    path,
+                    &["policies", name],
+                    format!("policy `{}` is not used by any mapping", name),
+                ));
+            }
+
+            for list in ["targets", "deny-targets"] {
+                let specs = raw
+                    .get("permits")
+                    .and_then(|p| p.get(list))
+                    .and_then(toml::Value::as_array)
+                    .map_or(&[][..], |a| &a[..]);
+
+                for spec in specs.iter().filter_map(toml::Value::as_str) {
+                    let unknown = match Target::parse(spec) {
+                        Ok(Target::User(Key::Name(n))) => {
+                            matches!(User::from_name(n), Ok(None)).then(|| "user")
+                        }
+                        Ok(Target::Group(g)) => {
+                            matches!(Group::from_name(g), Ok(None)).then(|| "group")
+                        }
+                        _ => None,
+                    };
+
+                    if let Some(kind) = unknown {
+                        diagnostics.push(self.diagnostic(
+                            Severity::Warning,
+                            path,
+                            &["policies", name, "permits", list],
+                            format!("unknown {} in `{}`: `{}`", kind, list, spec),
+                        ));
+                    }
+                }
+            }
+        }
+
+        self.diagnostics.append(&mut diagnostics);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::permits::CommandRule;
+
+    /// Create a fresh directory containing the given files.
+    fn write_files(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("mk-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        for (path, contents) in files {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+
+        dir
+    }
+
+    #[test]
+    fn test_includes() {
+        let dir = write_files(
+            "includes",
+            &[
+                (
+                    "mk.conf",
+                    "include = ['extra.conf']\ninclude-dir = ['mk.d']\n\
+                     [policies.default.permits]\nall-targets = true",
+                ),
+                ("extra.conf", "service = 'Pwd'\n[users]\nalice = 'default'"),
+                ("mk.d/10-web.toml", "[policies.web]\n[groups]\nweb = 'web'"),
+                ("mk.d/20-db.toml", "[policies.db]\n[groups]\ndb = 'db'"),
+                ("mk.d/.hidden.toml", "[policies.db]"),
+                ("mk.d/README", "[policies.db]"),
+            ],
+        );
+
+        let config = Config::from_file_unchecked(dir.join("mk.conf")).unwrap();
+        let mut policies: Vec<_> = config.policies.keys().cloned().collect();
+        policies.sort();
+
+        assert_eq!(policies, ["db", "default", "web"]);
+        assert_eq!(config.users["alice"], Mapping::new("default"));
+        assert_eq!(config.groups["web"], Mapping::new("web"));
+        assert!(matches!(config.service, AuthService::Pwd));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_duplicate_definitions() {
+        let dir = write_files(
+            "duplicates",
+            &[
+                ("mk.conf", "include-dir = ['mk.d']\n[users]\nalice = 'a'"),
+                ("mk.d/a.toml", "[policies.a]"),
+                ("mk.d/b.toml", "[policies.b]\n[users]\nalice = 'b'"),
+            ],
+        );
+
+        let err = Config::from_file_unchecked(dir.join("mk.conf")).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("user mapping `alice` is already defined"));
+
+        fs::write(dir.join("mk.d/b.toml"), "[policies.a]").unwrap();
+        let err = Config::from_file_unchecked(dir.join("mk.conf")).unwrap_err();
+        assert!(err.to_string().contains("policy `a` is already defined"));
+
+        fs::write(dir.join("mk.d/b.toml"), "include = ['../mk.conf']").unwrap();
+        let err = Config::from_file_unchecked(dir.join("mk.conf")).unwrap_err();
+        assert!(err.to_string().contains("included more than once"));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_extends() {
+        let dir = write_files(
+            "extends",
+            &[
+                (
+                    "mk.conf",
+                    "include = ['ops.conf']\n\
+                     [policies.default]\nhosts = ['web-*']\nvalid-until = 2026-06-30\n\
+                     [policies.default.permits]\ntargets = ['root']\n\
+                     [policies.default.session]\nrefresh = 5\n\
+                     [policies.relaxed]\nextends = 'ops'\n\
+                     [policies.relaxed.session]\nno_auth = true",
+                ),
+                (
+                    "ops.conf",
+                    "[policies.ops]\nextends = 'default'\n\
+                     [policies.ops.permits]\ntargets = ['www-data']",
+                ),
+            ],
+        );
+
+        let config = Config::from_file_unchecked(dir.join("mk.conf")).unwrap();
+
+        let ops = &config.policies["ops"];
+        assert_eq!(ops.extends.as_deref(), Some("default"));
+        assert_eq!(ops.permits.targets, ["www-data"]);
+        assert_eq!(ops.hosts, Some(vec!["web-*".to_owned()]));
+        assert_eq!(ops.valid_until, Some("2026-06-30".parse().unwrap()));
+        assert_eq!(ops.session.refresh, Some(Duration::from_secs(300)));
+        assert!(!ops.session.no_auth);
+
+        let relaxed = &config.policies["relaxed"];
+        assert_eq!(relaxed.permits.targets, ["www-data"]);
+        assert_eq!(relaxed.session.refresh, Some(Duration::from_secs(300)));
+        assert!(relaxed.session.no_auth);
+
+        fs::write(dir.join("ops.conf"), "[policies.ops]\nextends = 'missing'").unwrap();
+        let err = Config::from_file_unchecked(dir.join("mk.conf")).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("policy `ops` extends undefined policy `missing`"));
+
+        fs::write(dir.join("ops.conf"), "[policies.ops]\nextends = 'relaxed'").unwrap();
+        let err = Config::from_file_unchecked(dir.join("mk.conf")).unwrap_err();
+        assert!(err.to_string().contains("policy inheritance cycle"));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_mapping_precedence() {
+        let mut config: Config = toml::from_str(
+            "[policies.web.permits]\ntargets = ['www-data']\n\
+             [policies.db.permits]\ntargets = ['postgres']\n\
+             [policies.db.session]\nrefresh = 5\n\
+             [policies.admin.permits]\nall-targets = true\n\
+             [users]\nalice = 'web'\n\
+             [groups]\ndb = 'db'\nweb = 'web'\nadmin = { policy = 'admin', priority = 10 }",
+        )
+        .unwrap();
+        let clock = Clock::new(tz::TimeZone::utc(), 0);
+
+        let resolve = |c: &Config, user: &str, groups: &[&str]| {
+            let groups: Vec<_> = groups.iter().map(|g| Subject::new(*g, 100)).collect();
+            c.resolve_user_policy(
+                &Subject::new(user, 1000),
+                &groups,
+                "host",
+                &clock,
+                &Origin::default(),
+            )
+            .unwrap()
+            .map(|p| p.into_owned())
+        };
+
+        // User mappings before group mappings
+        let p = resolve(&config, "alice", &["db"]).unwrap();
+        assert_eq!(p.permits.targets, ["www-data"]);
+
+        // Group mappings in the order they were defined, regardless of membership order
+        let p = resolve(&config, "bob", &["web", "db"]).unwrap();
+        assert_eq!(p.permits.targets, ["postgres"]);
+
+        // Higher priorities first
+        let p = resolve(&config, "alice", &["web", "admin"]).unwrap();
+        assert!(p.permits.all_targets);
+
+        assert!(resolve(&config, "eve", &["users"]).is_none());
+
+        let matched = |c: &Config, groups: &[&str]| {
+            let groups: Vec<_> = groups.iter().map(|g| Subject::new(*g, 100)).collect();
+            c.resolve_user(
+                &Subject::new("alice", 1000),
+                &groups,
+                "host",
+                &clock,
+                &Origin::default(),
+            )
+            .unwrap()
+            .unwrap()
+            .mappings
+            .into_iter()
+            .map(|m| (m.kind, m.name, m.policy))
+            .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            matched(&config, &["admin"]),
+            [(MappingKind::Group, "admin".into(), "admin".into())]
+        );
+
+        config.resolution = Resolution::Merge;
+        let p = resolve(&config, "alice", &["db"]).unwrap();
+        let targets: Vec<_> = p.permits.sources().map(|p| p.targets.clone()).collect();
+        assert_eq!(targets, [["www-data"], ["postgres"]]);
+        assert_eq!(p.session.refresh, None);
+        assert_eq!(
+            matched(&config, &["db"]),
+            [
+                (MappingKind::User, "alice".into(), "web".into()),
+                (MappingKind::Group, "db".into(), "db".into())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_id_mappings() {
+        let config: Config = toml::from_str(
+            "[policies.service.permits]\ntargets = ['svc']\n\
+             [policies.named.permits]\ntargets = ['named']\n\
+             [policies.exact.permits]\ntargets = ['exact']\n\
+             [users]\n'#2000-2999' = 'service'\n'#2042' = 'exact'\nbackup = 'named'\n\
+             [groups]\n'#500-599' = 'service'",
+        )
+        .unwrap();
+        let clock = Clock::new(tz::TimeZone::utc(), 0);
+
+        let resolve = |user: Subject, groups: &[Subject]| {
+            config
+                .resolve_user_policy(&user, groups, "host", &clock, &Origin::default())
+                .unwrap()
+                .map(|p| p.permits.targets.clone())
+        };
+
+        assert_eq!(
+            resolve(Subject::new("svc-a", 2001), &[]),
+            Some(vec!["svc".into()])
+        );
+        assert_eq!(
+            resolve(Subject::new("svc-b", 2042), &[]),
+            Some(vec!["exact".into()])
+        );
+        assert_eq!(
+            resolve(Subject::new("backup", 2043), &[]),
+            Some(vec!["named".into()])
+        );
+        assert_eq!(
+            resolve(Subject::new("app", 3000), &[Subject::new("apps", 550)]),
+            Some(vec!["svc".into()])
+        );
+        assert_eq!(resolve(Subject::new("app", 3000), &[]), None);
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let config: Config = toml::from_str(
+            "[policies.ops.permits]\ntargets = ['%services']\ndeny-targets = ['backup']\n\
+             commands = ['/usr/bin/*']\ndeny-commands = ['/usr/bin/*sh']\n\
+             [policies.ops.session]\nno-auth = true\nalways-auth-commands = ['/usr/bin/passwd']\n\
+             [groups]\nops = 'ops'",
+        )
+        .unwrap();
+        let clock = Clock::new(tz::TimeZone::utc(), 0);
+
+        let alice = Subject::new("alice", 1000);
+        let ops = [Subject::new("ops", 100)];
+        let services = [Subject::new("services", 200)];
+        let www = Subject::new("www-data", 33);
+        let backup = Subject::new("backup", 34);
+
+        let evaluate = |invoker: &Subject, groups: &[Subject], target: &Subject, command: &str| {
+            let decision = config.evaluate(&Request {
+                invoker,
+                groups,
+                target,
+                target_groups: &services,
+                command: Path::new(command),
+                args: &[],
+                host: "host",
+                clock: &clock,
+                origin: &Origin::default(),
+            });
+            (decision.verdict, decision.rule, decision.auth_required)
+        };
+
+        assert_eq!(
+            evaluate(&alice, &ops, &www, "/usr/bin/id"),
+            (
+                Verdict::Allow,
+                Rule::Permitted {
+                    target: TargetRule::Rule("%services".into()),
+                    command: Some(crate::permits::CommandRule::new("/usr/bin/*")),
+                },
+                false
+            )
+        );
+        assert!(evaluate(&alice, &ops, &www, "/usr/bin/passwd").2);
+        assert_eq!(
+            evaluate(&alice, &ops, &backup, "/usr/bin/id").1,
+            Rule::DeniedTarget("backup".into())
+        );
+        assert_eq!(
+            evaluate(&alice, &ops, &www, "/usr/bin/bash").1,
+            Rule::DeniedCommand(crate::permits::CommandRule::new("/usr/bin/*sh"))
+        );
+        assert_eq!(
+            evaluate(&alice, &ops, &www, "/opt/bin/id").1,
+            Rule::UnpermittedCommand
+        );
+        assert_eq!(
+            evaluate(&alice, &[], &www, "/usr/bin/id"),
+            (Verdict::Deny, Rule::NoPolicy(None), false)
+        );
+        assert_eq!(
+            evaluate(&Subject::new("root", 0), &[], &backup, "/opt/bin/id"),
+            (Verdict::Allow, Rule::Root, false)
+        );
+    }
+
+    #[test]
+    fn test_origin() {
+        let config: Config = toml::from_str(
+            "[policies.console.permits]\nall-targets = true\n\
+             [policies.console.origin]\nttys = ['/dev/tty[0-9]*']\nallow-ssh = false\n\
+             [policies.remote.permits]\ntargets = ['deploy']\n\
+             [users]\nalice = { policy = 'console', priority = 10 }\n\
+             [groups]\nops = 'remote'",
+        )
+        .unwrap();
+        let clock = Clock::new(tz::TimeZone::utc(), 0);
+
+        let resolve = |groups: &[Subject], tty: &str, ssh: bool| {
+            let origin = Origin {
+                tty: Some(tty.into()),
+                ssh: Some(ssh),
+            };
+            config
+                .resolve_user_policy(
+                    &Subject::new("alice", 1000),
+                    groups,
+                    "db-1",
+                    &clock,
+                    &origin,
+                )
+                .map(|p| p.map(|p| p.permits.all_targets))
+        };
+
+        let ops = [Subject::new("ops", 100)];
+        assert!(resolve(&[], "/dev/tty1", false).unwrap().unwrap());
+        assert!(!resolve(&ops, "/dev/pts/0", false).unwrap().unwrap());
+        assert!(!resolve(&ops, "/dev/tty1", true).unwrap().unwrap());
+
+        let err = resolve(&[], "/dev/pts/0", false).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "policy console is not available from terminal /dev/pts/0"
+        );
+    }
+
+    #[test]
+    fn test_explain() {
+        let config: Config = toml::from_str(
+            "service = 'Pwd'\n\
+             [policies.web]\nhosts = ['web-*']\n\
+             [policies.ops.permits]\ntargets = ['deploy', 'root']\ndeny-commands = ['/bin/sh']\n\
+             [users]\nalice = 'web'\n\
+             [groups]\nops = 'ops'",
+        )
+        .unwrap();
+        let clock = Clock::new(tz::TimeZone::utc(), 0);
+
+        let mut trace = Vec::new();
+        let decision = config.explain(
+            &Request {
+                invoker: &Subject::new("alice", 1000),
+                groups: &[Subject::new("ops", 100), Subject::new("users", 101)],
+                target: &Subject::new("root", 0),
+                target_groups: &[],
+                command: Path::new("/usr/bin/id"),
+                args: &[],
+                host: "db-1",
+                clock: &clock,
+                origin: &Origin::default(),
+            },
+            &mut trace,
+        );
+
+        assert!(decision.is_allowed());
+        assert_eq!(
+            trace,
+            [
+                "user alice (#1000): `alice` -> policy web",
+                "group ops (#100): `ops` -> policy ops",
+                "group users (#101): no mapping",
+                "user mapping `alice` -> policy web (priority 0): skipped, policy web does not \
+                 apply on host db-1",
+                "group mapping `ops` -> policy ops (priority 0): used",
+                "targets `deploy`: no match",
+                "targets `root`: matches root",
+                "deny-commands `/bin/sh`: no match",
+                "commands: all permitted",
+                "session: authentication with Pwd required",
+                "permitted: targets rule `root`, all commands",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_permits() {
+        let clock = Clock::new(tz::TimeZone::utc(), 0);
+        let check =
+            |permits: &str, target: Subject, groups: &[Subject], command: &str, args: &[&str]| {
+                let permits: Permits = toml::from_str(permits).unwrap();
+                let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+                let request = Request {
+                    invoker: &Subject::new("alice", 1000),
+                    groups: &[],
+                    target: &target,
+                    target_groups: groups,
+                    command: Path::new(command),
+                    args: &args,
+                    host: "host",
+                    clock: &clock,
+                    origin: &Origin::default(),
+                };
+                check_permits(&permits, &[], &request, &mut Vec::new()).map(|(_, rule)| rule)
+            };
+
+        let commands = "all-targets = true\n\
+             commands = ['/usr/bin/id', { command = '/usr/bin/systemctl', args = ['status'] }]";
+        let www = || Subject::new("www-data", 33);
+        assert!(check(commands, www(), &[], "/usr/bin/id", &["-u"]).is_ok());
+        assert!(check(commands, www(), &[], "/usr/bin/systemctl", &["status"]).is_ok());
+        assert_eq!(
+            check(commands, www(), &[], "/usr/bin/systemctl", &["stop"]),
+            Err(Rule::UnpermittedCommand)
+        );
+        assert_eq!(
+            check(commands, www(), &[], "/bin/sh", &[]),
+            Err(Rule::UnpermittedCommand)
+        );
+        assert_eq!(
+            check("all-targets = true", www(), &[], "/bin/sh", &[]),
+            Ok(Rule::Permitted {
+                target: TargetRule::All,
+                command: None
+            })
+        );
+
+        let deny = "all-targets = true\ndeny-targets = ['root', 'backup']\n\
+             deny-commands = ['/bin/*sh', { command = '/usr/bin/systemctl', args-prefix = ['stop'] }]";
+        assert_eq!(
+            check(deny, Subject::new("root", 0), &[], "/usr/bin/id", &[]),
+            Err(Rule::DeniedTarget("root".into()))
+        );
+        match check(deny, www(), &[], "/usr/bin/systemctl", &["stop", "nginx"]) {
+            Err(Rule::DeniedCommand(rule)) => {
+                assert_eq!(rule.to_string(), "/usr/bin/systemctl stop ...")
+            }
+            r => panic!("unexpected {:?}", r),
+        }
+        assert!(matches!(
+            check(deny, www(), &[], "/bin/bash", &[]),
+            Err(Rule::DeniedCommand(_))
+        ));
+        assert!(check(deny, www(), &[], "/usr/bin/systemctl", &["status"]).is_ok());
+
+        let targets = "targets = ['deploy', '%services', '#100-999']\ndeny-targets = ['#0']";
+        let services = [Subject::new("services", 900)];
+        assert_eq!(
+            check(targets, Subject::new("deploy", 1001), &[], "/bin/sh", &[]),
+            Ok(Rule::Permitted {
+                target: TargetRule::Rule("deploy".into()),
+                command: None
+            })
+        );
+        assert!(check(
+            targets,
+            Subject::new("nginx", 1002),
+            &services,
+            "/bin/sh",
+            &[]
+        )
+        .is_ok());
+        assert!(check(targets, Subject::new("postgres", 120), &[], "/bin/sh", &[]).is_ok());
+        assert_eq!(
+            check(targets, Subject::new("bob", 1001), &[], "/bin/sh", &[]),
+            Err(Rule::UnpermittedTarget)
+        );
+        assert_eq!(
+            check(targets, Subject::new("toor", 0), &[], "/bin/sh", &[]),
+            Err(Rule::DeniedTarget("#0".into()))
+        );
+
+        // Running as self needs no targets rule
+        let rule = check(targets, Subject::new("alice", 1000), &[], "/bin/sh", &[]).unwrap();
+        assert_eq!(rule.to_string(), "running as self, all commands");
+        let rule = check("all-targets = true", www(), &[], "/bin/sh", &[]).unwrap();
+        assert_eq!(rule.to_string(), "all targets, all commands");
+    }
+
+    #[test]
+    fn test_merged_permits() {
+        let config: Config = toml::from_str(
+            "resolution = 'merge'\n\
+             [policies.status.permits]\ntargets = ['root']\n\
+             commands = [{ command = '/usr/bin/systemctl', args-prefix = ['status'] }]\n\
+             [policies.backup.permits]\ntargets = ['backup']\ndeny-commands = ['/bin/rm']\n\
+             [users]\nalice = 'status'\n\
+             [groups]\nops = 'backup'",
+        )
+        .unwrap();
+        let clock = Clock::new(tz::TimeZone::utc(), 0);
+
+        let explain = |target: Subject, command: &str, args: &[String]| {
+            let mut trace = Vec::new();
+            let decision = config.explain(
+                &Request {
+                    invoker: &Subject::new("alice", 1000),
+                    groups: &[Subject::new("ops", 100)],
+                    target: &target,
+                    target_groups: &[],
+                    command: Path::new(command),
+                    args,
+                    host: "host",
+                    clock: &clock,
+                    origin: &Origin::default(),
+                },
+                &mut trace,
+            );
+            (decision.rule, trace)
+        };
+
+        let root = || Subject::new("root", 0);
+        let backup = || Subject::new("backup", 34);
+
+        // Neither policy permits running any command as root
+        let (rule, trace) = explain(root(), "/bin/sh", &[]);
+        assert_eq!(rule, Rule::UnpermittedCommand);
+        assert_eq!(
+            trace[trace.len() - 8..],
+            [
+                "policy status:",
+                "targets `root`: matches root",
+                "policy backup:",
+                "targets `backup`: no match",
+                "deny-commands `/bin/rm`: no match",
+                "policy status:",
+                "commands `/usr/bin/systemctl status ...`: no match",
+                "denied: command not permitted",
+            ]
+        );
+
+        let status = ["status".to_string(), "sshd".into()];
+        assert!(matches!(
+            explain(root(), "/usr/bin/systemctl", &status).0,
+            Rule::Permitted { .. }
+        ));
+        assert!(matches!(
+            explain(backup(), "/bin/sh", &[]).0,
+            Rule::Permitted { command: None, .. }
+        ));
+
+        // Deny rules of every policy apply
+        assert_eq!(
+            explain(root(), "/bin/rm", &[]).0,
+            Rule::DeniedCommand(CommandRule::new("/bin/rm"))
+        );
+        assert_eq!(
+            explain(Subject::new("www-data", 33), "/bin/sh", &[]).0,
+            Rule::UnpermittedTarget
+        );
+    }
+
+    #[test]
+    fn test_merged_rules() {
+        let config: Config = toml::from_str(
+            "resolution = 'merge'\n\
+             [policies.systemctl.permits]\ntargets = ['root']\ncommands = ['/usr/bin/systemctl']\n\
+             [policies.web]\npermits = { targets = ['www-data'] }\nenv = { keep = ['*'] }\n\
+             process = { capabilities = ['CAP_NET_BIND_SERVICE'], allow-chdir = ['/srv/*'] }\n\
+             [users]\nalice = 'systemctl'\n\
+             [groups]\nweb = 'web'",
+        )
+        .unwrap();
+        let clock = Clock::new(tz::TimeZone::utc(), 0);
+
+        let explain = |target: Subject, command: &str| {
+            let mut trace = Vec::new();
+            let decision = config.explain(
+                &Request {
+                    invoker: &Subject::new("alice", 1000),
+                    groups: &[Subject::new("web", 100)],
+                    target: &target,
+                    target_groups: &[],
+                    command: Path::new(command),
+                    args: &[],
+                    host: "host",
+                    clock: &clock,
+                    origin: &Origin::default(),
+                },
+                &mut trace,
+            );
+            assert_eq!(decision.verdict, Verdict::Allow);
+            let policy = decision.policy.unwrap().policy;
+            (policy.env.clone(), policy.process.clone(), trace)
+        };
+
+        // Variables kept and capabilities granted for www-data must not be for commands run as root
+        let (env, process, trace) = explain(Subject::new("root", 0), "/usr/bin/systemctl");
+        assert!(!env.allows("LD_PRELOAD"));
+        assert!(process.capabilities.is_empty());
+        assert!(process.allow_chdir.is_empty());
+        assert!(trace.contains(&"policy systemctl: env and process rules apply".to_string()));
+
+        let (env, process, trace) = explain(Subject::new("www-data", 33), "/bin/sh");
+        assert!(env.allows("LD_PRELOAD"));
+        assert_eq!(process.capabilities, ["CAP_NET_BIND_SERVICE"]);
+        assert!(trace.contains(&"policy web: env and process rules apply".to_string()));
+    }
+
+    #[test]
+    fn test_check() {
+        let dir = write_files(
+            "check",
+            &[
+                (
+                    "mk.conf",
+                    "include = ['extra.conf']\n\
+                     [policies.default.permits]\n\
+                     targets = ['root', 'mk-no-such-user']\n\
+                     deny-comands = ['/bin/sh']\n\
+                     [users]\nroot = 'default'\nmk-nobody = { policy = 'missing', prority = 1 }",
+                ),
+                (
+                    "extra.conf",
+                    "[policies.unused]\n[groups]\n'#0' = 'default'",
+                ),
+            ],
+        );
+        let path = dir.join("mk.conf");
+
+        let loader = Loader {
+            unchecked: true,
+            ..Loader::checking()
+        };
+        let found: Vec<_> = loader
+            .check(&path, |l| l.load(&path))
+            .into_iter()
+            .map(|d| {
+                (
+                    d.path.file_name().unwrap().to_owned(),
+                    d.position,
+                    d.severity,
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            found,
+            [
+                ("extra.conf".into(), Some((1, 1)), Severity::Warning),
+                ("mk.conf".into(), Some((3, 1)), Severity::Warning),
+                ("mk.conf".into(), Some((4, 1)), Severity::Error),
+                ("mk.conf".into(), Some((7, 1)), Severity::Error),
+                ("mk.conf".into(), Some((7, 1)), Severity::Error),
+                ("mk.conf".into(), Some((7, 1)), Severity::Warning),
+            ]
+        );
+
+        fs::write(&path, "[users]\nalice = ").unwrap();
+        let found = Config::check_file(&path);
+        assert_eq!(found.last().unwrap().position, Some((2, 9)));
+
+        fs::write(&path, "[policies.a]\nvalid-until = 2026-02-30").unwrap();
+        let found = Config::check_file(&path);
+        assert_eq!(found.last().unwrap().position, Some((2, 1)));
+        assert_eq!(found.last().unwrap().severity, Severity::Error);
+
+        // Unknown keys are also rejected when loading
+        fs::write(
+            &path,
+            "[policies.a.permits]\ntargets = ['root']\ncomands = ['/bin/id']",
+        )
+        .unwrap();
+        match Config::from_file_unchecked(&path).unwrap_err() {
+            Error::Config(d) => {
+                assert_eq!(d.position, Some((3, 1)));
+                assert_eq!(d.message, "unknown key `policies.a.permits.comands`");
+            }
+            e => panic!("unexpected error: {}", e),
+        }
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_policy_service() {
+        let dir = write_files(
+            "service",
+            &[(
+                "mk.conf",
+                "service = 'Pwd'\n\
+                 [policies.default]\n\
+                 [policies.mfa.auth]\nservice = 'Pam'",
+            )],
+        );
+        let path = dir.join("mk.conf");
+
+        match Config::from_file_unchecked(&path) {
+            Ok(config) => {
+                assert!(AuthService::Pam.is_available());
+                assert_eq!(
+                    config.service_for(&config.policies["default"]),
+                    AuthService::Pwd
+                );
+                assert_eq!(
+                    config.service_for(&config.policies["mfa"]),
+                    AuthService::Pam
+                );
+            }
+            Err(Error::Config(d)) => {
+                assert!(!AuthService::Pam.is_available());
+                assert_eq!(d.position, Some((4, 1)));
+            }
+            Err(e) => panic!("{}", e),
+        }
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_pam_service() {
+        let dir = write_files(
+            "pam-service",
+            &[
+                (
+                    "mk.conf",
+                    "pam-service = 'mk-common'\n\
+                     [policies.default]\n\
+                     [policies.mfa.auth]\npam-service = 'mk-mfa'",
+                ),
+                ("bad.conf", "[policies.default.auth]\npam-service = '../mk'"),
+            ],
+        );
+
+        let config = Config::from_file_unchecked(dir.join("mk.conf")).unwrap();
+        assert_eq!(
+            config
+                .auth_rules_for(&config.policies["default"])
+                .pam_service,
+            Some("mk-common".into())
+        );
+        assert_eq!(
+            config.auth_rules_for(&config.policies["mfa"]).pam_service,
+            Some("mk-mfa".into())
+        );
+
+        match Config::from_file_unchecked(dir.join("bad.conf")).unwrap_err() {
+            Error::Config(d) => assert_eq!(d.position, Some((2, 1))),
+            e => panic!("{}", e),
+        }
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_insecure_files() {
+        let dir = write_files("insecure", &[("mk.conf", "[policies.default]")]);
+        let path = dir.join("mk.conf");
+
+        // The temporary directory is writable by others
+        let err = Config::from_file(&path).unwrap_err();
+        assert!(err.to_string().contains("refusing to read"));
+
+        utils::set_mode(&path, 0o666).unwrap();
+        let err = utils::check_secure_path(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+
+        let link = dir.join("link.conf");
+        std::os::unix::fs::symlink(&path, &link).unwrap();
+        let err = utils::check_secure_path(&link).unwrap_err();
+        assert!(err.to_string().ends_with("is a symbolic link"));
 
-        Ok(None)
+        fs::remove_dir_all(dir).unwrap();
     }
 }
@@ -0,0 +1,134 @@
+//! Requests to run commands, and the decisions made for them.
+//!
+//! Decisions are made by [`Config::evaluate`](crate::config::Config::evaluate), which never
+//! queries the system, so that everything it needs must be part of the [`Request`].
+
+use std::fmt;
+use std::path::Path;
+
+use crate::config::ResolvedPolicy;
+use crate::mapping::Subject;
+use crate::origin::Origin;
+use crate::permits::CommandRule;
+use crate::schedule::Clock;
+
+/// A request to run a command as a target user.
+#[derive(Debug, Clone, Copy)]
+pub struct Request<'a> {
+    /// User who invoked `mk`.
+    pub invoker: &'a Subject,
+    /// Groups the invoker is a member of.
+    pub groups: &'a [Subject],
+    /// User to run the command as.
+    pub target: &'a Subject,
+    /// Groups the target is a member of.
+    pub target_groups: &'a [Subject],
+    /// Absolute path of the executable.
+    pub command: &'a Path,
+    /// Arguments passed to the executable.
+    pub args: &'a [String],
+    /// Name of the host the command is run on.
+    pub host: &'a str,
+    /// Time at which the command is run.
+    pub clock: &'a Clock,
+    /// Where the invoker is running `mk` from.
+    pub origin: &'a Origin,
+}
+
+/// Whether a request is allowed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Allow,
+    Deny,
+}
+
+/// The rule that decided a request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Rule {
+    /// The invoker is root, who is permitted everything.
+    Root,
+    /// No policy applies to the invoker. Holds the reason if a policy was skipped because it was
+    /// not valid at the time of the request.
+    NoPolicy(Option<String>),
+    /// The target is denied by a `deny-targets` rule.
+    DeniedTarget(String),
+    /// The target is not permitted.
+    UnpermittedTarget,
+    /// The command is denied by a `deny-commands` rule.
+    DeniedCommand(CommandRule),
+    /// The command is not permitted.
+    UnpermittedCommand,
+    /// The request is permitted.
+    Permitted {
+        /// What permits the target.
+        target: TargetRule,
+        /// The `commands` rule that permits the command. This is [`None`] if all commands are
+        /// permitted.
+        command: Option<CommandRule>,
+    },
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Root => write!(f, "invoked by root"),
+            Self::NoPolicy(Some(r)) => write!(f, "no policy applies ({})", r),
+            Self::NoPolicy(None) => write!(f, "no policy applies"),
+            Self::DeniedTarget(t) => write!(f, "deny-targets rule `{}`", t),
+            Self::UnpermittedTarget => write!(f, "target not permitted"),
+            Self::DeniedCommand(c) => write!(f, "deny-commands rule `{}`", c),
+            Self::UnpermittedCommand => write!(f, "command not permitted"),
+            Self::Permitted { target, command } => {
+                write!(f, "{}", target)?;
+                match command {
+                    Some(c) => write!(f, ", commands rule `{}`", c),
+                    None => write!(f, ", all commands"),
+                }
+            }
+        }
+    }
+}
+
+/// What permits the target of a request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TargetRule {
+    /// The target is the invoker, who may always run permitted commands as themself.
+    Invoker,
+    /// All targets are permitted.
+    All,
+    /// The `targets` rule that permits the target.
+    Rule(String),
+}
+
+impl fmt::Display for TargetRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Invoker => write!(f, "running as self"),
+            Self::All => write!(f, "all targets"),
+            Self::Rule(t) => write!(f, "targets rule `{}`", t),
+        }
+    }
+}
+
+/// The decision made for a [`Request`].
+#[derive(Debug, Clone)]
+pub struct Decision<'a> {
+    /// Whether the request is allowed.
+    pub verdict: Verdict,
+    /// The policy the decision was made with, if any applies to the invoker.
+    pub policy: Option<ResolvedPolicy<'a>>,
+    /// The rule that decided the request.
+    pub rule: Rule,
+    /// Whether the invoker must authenticate before the command is run. This is never set for
+    /// denied requests.
+    pub auth_required: bool,
+}
+
+impl Decision<'_> {
+    /// Check if the request is allowed.
+    #[must_use]
+    #[inline]
+    pub fn is_allowed(&self) -> bool {
+        self.verdict == Verdict::Allow
+    }
+}
@@ -0,0 +1,189 @@
+//! Configuration diagnostics.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// How severe a diagnostic is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// The configuration may not behave as intended.
+    Warning,
+    /// The configuration is invalid.
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Warning => write!(f, "warning"),
+            Self::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A problem found in a configuration file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// File the problem was found in.
+    pub path: PathBuf,
+    /// One-based line and column of the problem, if known.
+    pub position: Option<(usize, usize)>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    #[must_use]
+    pub fn new<P: Into<PathBuf>, S: Into<String>>(
+        severity: Severity,
+        path: P,
+        position: Option<(usize, usize)>,
+        message: S,
+    ) -> Self {
+        Self {
+            severity,
+            path: path.into(),
+            position,
+            message: message.into(),
+        }
+    }
+
+    /// Create a diagnostic for a key in a file with the given contents.
+    ///
+    /// See [`locate`].
+    #[must_use]
+    pub fn at_key<S: Into<String>>(
+        severity: Severity,
+        path: &Path,
+        contents: &str,
+        key: &[&str],
+        message: S,
+    ) -> Self {
+        Self::new(severity, path, locate(contents, key), message)
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.path.display())?;
+        if let Some((line, column)) = self.position {
+            write!(f, ":{}:{}", line, column)?;
+        }
+        write!(f, ": {}: {}", self.severity, self.message)
+    }
+}
+
+impl std::error::Error for Diagnostic {}
+
+/// Split a dotted TOML key into its parts, removing quotes.
+fn split_key(key: &str) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut quote = None;
+
+    for c in key.chars() {
+        match (c, quote) {
+            ('"', None) | ('\'', None) => quote = Some(c),
+            (c, Some(q)) if c == q => quote = None,
+            ('.', None) => parts.push(String::new()),
+            (c, None) if c.is_whitespace() => {}
+            (c, _) => parts.last_mut().unwrap().push(c),
+        }
+    }
+
+    parts
+}
+
+/// Find the one-based line and column at which a key is defined in a TOML document.
+///
+/// This returns the first table header or key whose full path begins with `key`, or failing that,
+/// the deepest enclosing key found. The search is a best effort, and does not handle every TOML
+/// construct.
+#[must_use]
+pub fn locate<S: AsRef<str>>(contents: &str, key: &[S]) -> Option<(usize, usize)> {
+    let key: Vec<&str> = key.iter().map(AsRef::as_ref).collect();
+    let mut table: Vec<String> = Vec::new();
+    let mut best: Option<(usize, (usize, usize))> = None;
+
+    for (n, line) in contents.lines().enumerate() {
+        let trimmed = line.trim_start();
+        let column = line.len() - trimmed.len() + 1;
+
+        let path = if trimmed.starts_with('[') {
+            let header = trimmed.trim_start_matches('[');
+            let header = match header.find(']') {
+                Some(i) => &header[..i],
+                None => continue,
+            };
+            table = split_key(header);
+            table.clone()
+        } else if let Some(i) = trimmed.find('=') {
+            if trimmed.starts_with('#') {
+                continue;
+            }
+            let mut path = table.clone();
+            path.extend(split_key(&trimmed[..i]));
+            path
+        } else {
+            continue;
+        };
+
+        let common = path.iter().zip(&key).take_while(|(a, b)| a == *b).count();
+
+        if common == key.len() {
+            return Some((n + 1, column));
+        }
+        // Only keys that enclose `key` are useful as a fallback
+        if common == path.len() && best.map_or(true, |(c, _)| common > c) {
+            best = Some((common, (n + 1, column)));
+        }
+    }
+
+    best.map(|(_, p)| p)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"service = "Pwd"
+
+[policies.default]
+hosts = ["web-*"]
+
+[policies.default.permits]
+  all-targets = true
+  "deny-targets" = ["root"]
+
+[groups]
+wheel = { policy = "default", prority = 1 }
+"#;
+
+    #[test]
+    fn test_locate() {
+        assert_eq!(locate(CONFIG, &["service"]), Some((1, 1)));
+        assert_eq!(locate(CONFIG, &["policies", "default"]), Some((3, 1)));
+        assert_eq!(
+            locate(CONFIG, &["policies", "default", "hosts"]),
+            Some((4, 1))
+        );
+        assert_eq!(
+            locate(CONFIG, &["policies", "default", "permits", "deny-targets"]),
+            Some((8, 3))
+        );
+        // Keys in inline tables resolve to the enclosing key
+        assert_eq!(
+            locate(CONFIG, &["groups", "wheel", "prority"]),
+            Some((11, 1))
+        );
+        assert_eq!(locate(CONFIG, &["users"]), None);
+    }
+
+    #[test]
+    fn test_display() {
+        let d = Diagnostic::new(Severity::Error, "/etc/mk.conf", Some((3, 1)), "oops");
+        assert_eq!(d.to_string(), "/etc/mk.conf:3:1: error: oops");
+
+        let d = Diagnostic::new(Severity::Warning, "/etc/mk.conf", None, "hmm");
+        assert_eq!(d.to_string(), "/etc/mk.conf: warning: hmm");
+    }
+}
@@ -0,0 +1,255 @@
+//! Environment of commands.
+//!
+//! Commands never inherit the invoker's environment, except when invoked by root. Instead, it is
+//! built from a few variables describing the target user, variables the invoker asked to keep and
+//! the policy allows, and variables the policy sets.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use indexmap::IndexMap;
+use nix::unistd::User;
+
+use crate::prelude::*;
+
+/// `PATH` of commands, unless set otherwise by a policy.
+pub const DEFAULT_PATH: &str = "/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin";
+
+/// Default field values.
+pub(crate) mod defaults {
+    use super::*;
+
+    #[inline]
+    pub const fn keep() -> Vec<String> {
+        Vec::new()
+    }
+
+    #[inline]
+    pub const fn deny() -> Vec<String> {
+        Vec::new()
+    }
+
+    #[inline]
+    pub fn set() -> IndexMap<String, String> {
+        IndexMap::new()
+    }
+
+    #[inline]
+    pub const fn file() -> Option<PathBuf> {
+        None
+    }
+}
+
+/// Rules for the environment of commands.
+///
+/// ```toml
+/// [policies.default.env]
+/// keep = ["EDITOR", "LC_*"]
+/// deny = ["LC_ALL"]
+/// set = { PATH = "/usr/bin:/bin" }
+/// file = "/etc/mk/environment"
+/// ```
+#[readonly::make]
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
+pub struct Rules {
+    /// Patterns of variables the invoker may keep from their environment, by naming them with
+    /// `-E`. Patterns are matched using [`utils::matches_pattern`].
+    #[serde(default = "defaults::keep")]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub keep: Vec<String>,
+    /// Patterns of variables that are never kept, even if they match `keep`.
+    #[serde(default = "defaults::deny")]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub deny: Vec<String>,
+    /// Variables set to fixed values. These take precedence over all other variables.
+    #[serde(default = "defaults::set")]
+    #[serde(skip_serializing_if = "IndexMap::is_empty")]
+    pub set: IndexMap<String, String>,
+    /// File from which to read additional variables, one `NAME=value` per line. The file must
+    /// only be modifiable by root (see [`utils::check_secure_path`]).
+    #[serde(default = "defaults::file")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<PathBuf>,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Self {
+            keep: defaults::keep(),
+            deny: defaults::deny(),
+            set: defaults::set(),
+            file: defaults::file(),
+        }
+    }
+}
+
+impl Rules {
+    /// Environment overrides for the root user, who may keep any variable. Commands run by root
+    /// keep all of them.
+    #[must_use]
+    pub fn root() -> Self {
+        Self {
+            keep: vec!["*".into()],
+            ..Self::default()
+        }
+    }
+
+    /// Check if a variable may be kept from the invoker's environment.
+    #[must_use]
+    pub fn allows(&self, name: &str) -> bool {
+        self.keep.iter().any(|p| utils::matches_pattern(p, name))
+            && !self.deny.iter().any(|p| utils::matches_pattern(p, name))
+    }
+
+    /// Build the environment of a command run as `target`.
+    ///
+    /// The environment starts with `HOME`, `LOGNAME`, `USER` and `SHELL` of the target, a `PATH`
+    /// of [`DEFAULT_PATH`], and `TERM` if the invoker has it set and it is not denied. The
+    /// variables named in `preserve` are then kept from `vars`, the invoker's environment,
+    /// followed by the variables in `file` and `set`.
+    ///
+    /// # Errors
+    ///
+    /// An [`io::Error`] of kind [`io::ErrorKind::PermissionDenied`] is returned if a variable in
+    /// `preserve` may not be kept. This also fails if the file could not be read, is invalid, or
+    /// could be modified by users other than root.
+    pub fn build(
+        &self,
+        target: &User,
+        vars: &HashMap<String, String>,
+        preserve: &[String],
+    ) -> Result<IndexMap<String, String>> {
+        let mut env = IndexMap::new();
+
+        env.insert("HOME".into(), target.dir.to_string_lossy().into_owned());
+        env.insert("LOGNAME".into(), target.name.clone());
+        env.insert("USER".into(), target.name.clone());
+        env.insert("SHELL".into(), target.shell.to_string_lossy().into_owned());
+        env.insert("PATH".into(), DEFAULT_PATH.into());
+
+        if let Some(term) = vars.get("TERM") {
+            if !self.deny.iter().any(|p| utils::matches_pattern(p, "TERM")) {
+                env.insert("TERM".into(), term.clone());
+            }
+        }
+
+        for name in preserve {
+            if !self.allows(name) {
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    format!("not permitted to preserve environment variable {}", name),
+                )
+                .into());
+            }
+            if let Some(v) = vars.get(name) {
+                env.insert(name.clone(), v.clone());
+            }
+        }
+
+        if let Some(file) = &self.file {
+            env.extend(read_file(file)?);
+        }
+
+        env.extend(self.set.iter().map(|(k, v)| (k.clone(), v.clone())));
+
+        Ok(env)
+    }
+}
+
+/// Read variables from a file.
+fn read_file(path: &Path) -> Result<Vec<(String, String)>> {
+    utils::check_secure_path(&utils::normalize_path(path))?;
+
+    parse(&fs::read_to_string(path)?).map_err(|(line, e)| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}:{}: {}", path.display(), line, e),
+        )
+        .into()
+    })
+}
+
+/// Parse `NAME=value` lines. Empty lines and lines starting with `#` are ignored, and values are
+/// taken literally.
+///
+/// # Errors
+///
+/// Returns the line number and a description of the first invalid line.
+fn parse(contents: &str) -> core::result::Result<Vec<(String, String)>, (usize, String)> {
+    let mut vars = Vec::new();
+
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim_start();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        match line.split_once('=') {
+            Some((name, value)) if is_name(name) => vars.push((name.into(), value.into())),
+            _ => return Err((i + 1, format!("expected `NAME=value`, found `{}`", line))),
+        }
+    }
+
+    Ok(vars)
+}
+
+/// Check if a string is a valid variable name.
+fn is_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build() {
+        let rules: Rules = toml::from_str(
+            "keep = ['EDITOR', 'LC_*']\ndeny = ['LC_ALL']\nset = { PATH = '/bin', MODE = 'x' }",
+        )
+        .unwrap();
+        let target = User::from_uid(nix::unistd::getuid()).unwrap().unwrap();
+
+        let vars: HashMap<_, _> = [
+            ("TERM", "xterm"),
+            ("EDITOR", "vi"),
+            ("LC_ALL", "C"),
+            ("LD_PRELOAD", "evil.so"),
+        ]
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+
+        let env = rules
+            .build(&target, &vars, &["EDITOR".into(), "LC_TIME".into()])
+            .unwrap();
+        assert_eq!(env["PATH"], "/bin");
+        assert_eq!(env["MODE"], "x");
+        assert_eq!(env["TERM"], "xterm");
+        assert_eq!(env["EDITOR"], "vi");
+        assert_eq!(env["USER"], target.name);
+        assert!(!env.contains_key("LC_TIME"));
+        assert!(!env.contains_key("LD_PRELOAD"));
+
+        for name in ["LC_ALL", "LD_PRELOAD"] {
+            assert!(rules.build(&target, &vars, &[name.into()]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            parse("# comment\n\nLANG=C.UTF-8\n  EMPTY=\nQUOTED=\"a b\"\n").unwrap(),
+            [
+                ("LANG".into(), "C.UTF-8".into()),
+                ("EMPTY".into(), "".into()),
+                ("QUOTED".into(), "\"a b\"".into()),
+            ]
+        );
+        assert_eq!(parse("A=1\nexport B=2").unwrap_err().0, 2);
+    }
+}
@@ -1,9 +1,10 @@
 //! Error types.
 
-use std::ffi::NulError;
 use std::io;
 use std::str::Utf8Error;
 
+use crate::diagnostics::Diagnostic;
+
 pub type Result<T> = core::result::Result<T, Error>;
 
 /// All error types that we handle.
@@ -17,6 +18,10 @@ pub enum Error {
     /// IO error.
     #[error("{0}")]
     Io(#[from] io::Error),
+
+    /// Invalid configuration.
+    #[error("{0}")]
+    Config(#[from] Diagnostic),
 }
 
 #[cfg(feature = "pam")]
@@ -40,9 +45,3 @@ impl From<Utf8Error> for Error {
         Self::Io(io::Error::new(io::ErrorKind::Other, e))
     }
 }
-
-impl From<NulError> for Error {
-    fn from(e: NulError) -> Self {
-        Self::Io(io::Error::new(io::ErrorKind::Other, e))
-    }
-}
@@ -0,0 +1,440 @@
+//! Conversion of [`doas.conf`](https://man.openbsd.org/doas.conf.5) rules.
+//!
+//! Each identity a rule applies to is mapped to its own policy. As `doas` uses the last rule
+//! matching a request, mappings are given priorities in the order their identities were last
+//! named. Rules that cannot be expressed exactly are skipped, except for deny rules, which are
+//! reported as errors since the converted configurations would permit more than the original
+//! rules did without them.
+
+use std::path::Path;
+
+use super::*;
+
+/// Path of the `doas` configuration file.
+pub const PATH: &str = "/etc/doas.conf";
+
+/// A lexical token.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Word(String),
+    Open,
+    Close,
+}
+
+/// Split the contents of a file into the tokens of each rule, along with the line each rule
+/// starts on.
+fn tokenize(contents: &str) -> Vec<(usize, core::result::Result<Vec<Token>, String>)> {
+    let mut rules = Vec::new();
+    let mut tokens = Vec::new();
+    let mut word: Option<String> = None;
+    let mut error = None;
+    let (mut line, mut start) = (1, 1);
+    let mut chars = contents.chars().peekable();
+
+    loop {
+        let c = chars.next();
+
+        if let Some(c) = c {
+            if word.is_some() && (c.is_whitespace() || "#{}".contains(c)) {
+                tokens.push(Token::Word(word.take().unwrap()));
+            }
+        }
+
+        match c {
+            None | Some('\n') => {
+                if let Some(w) = word.take() {
+                    tokens.push(Token::Word(w));
+                }
+                if let Some(e) = error.take() {
+                    rules.push((start, Err(e)));
+                    tokens.clear();
+                } else if !tokens.is_empty() {
+                    rules.push((start, Ok(std::mem::take(&mut tokens))));
+                }
+                if c.is_none() {
+                    break;
+                }
+                line += 1;
+                start = line;
+            }
+            Some('#') => {
+                while chars.peek().map_or(false, |c| *c != '\n') {
+                    chars.next();
+                }
+            }
+            Some('{') => tokens.push(Token::Open),
+            Some('}') => tokens.push(Token::Close),
+            Some('\\') => match chars.next() {
+                Some('\n') => line += 1,
+                Some(c) => word.get_or_insert_with(String::new).push(c),
+                None => {
+                    error.get_or_insert_with(|| "unexpected end of file".into());
+                }
+            },
+            Some('"') => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.peek() {
+                        Some('\n') | None => {
+                            error.get_or_insert_with(|| "unterminated quotes".into());
+                            break;
+                        }
+                        Some('"') => {
+                            chars.next();
+                            break;
+                        }
+                        Some('\\') => {
+                            chars.next();
+                            match chars.next() {
+                                Some('\n') => line += 1,
+                                Some(c) => word.push(c),
+                                None => {}
+                            }
+                        }
+                        Some(_) => word.push(chars.next().unwrap()),
+                    }
+                }
+            }
+            Some(c) if c.is_whitespace() => {}
+            Some(c) => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+
+    rules
+}
+
+/// A parsed rule.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Rule {
+    permit: bool,
+    nopass: bool,
+    nolog: bool,
+    persist: bool,
+    keepenv: bool,
+    setenv: Vec<String>,
+    /// User name or UID, or group name or GID prefixed with `:`.
+    identity: String,
+    target: Option<String>,
+    cmd: Option<String>,
+    /// Exact arguments, if restricted.
+    args: Option<Vec<String>>,
+}
+
+/// Take the next token, which must be a word.
+fn word<I: Iterator<Item = Token>>(
+    tokens: &mut I,
+    what: &str,
+) -> core::result::Result<String, String> {
+    match tokens.next() {
+        Some(Token::Word(w)) => Ok(w),
+        Some(_) => Err(format!("expected {}, found a brace", what)),
+        None => Err(format!("expected {}", what)),
+    }
+}
+
+/// Parse the tokens of a rule.
+fn parse(tokens: Vec<Token>) -> core::result::Result<Rule, String> {
+    let mut tokens = tokens.into_iter();
+
+    let mut rule = Rule {
+        permit: match &word(&mut tokens, "`permit` or `deny`")?[..] {
+            "permit" => true,
+            "deny" => false,
+            w => return Err(format!("expected `permit` or `deny`, found `{}`", w)),
+        },
+        ..Rule::default()
+    };
+
+    loop {
+        let next = word(&mut tokens, "an identity")?;
+        match &next[..] {
+            "nopass" => rule.nopass = true,
+            "nolog" => rule.nolog = true,
+            "persist" => rule.persist = true,
+            "keepenv" => rule.keepenv = true,
+            "setenv" => {
+                if tokens.next() != Some(Token::Open) {
+                    return Err("expected `{` after `setenv`".into());
+                }
+                loop {
+                    match tokens.next() {
+                        Some(Token::Word(w)) => rule.setenv.push(w),
+                        Some(Token::Close) => break,
+                        _ => return Err("expected `}` after `setenv` variables".into()),
+                    }
+                }
+            }
+            _ => {
+                rule.identity = next;
+                break;
+            }
+        }
+    }
+
+    let mut next = tokens.next();
+    if next == Some(Token::Word("as".into())) {
+        rule.target = Some(word(&mut tokens, "a target")?);
+        next = tokens.next();
+    }
+    if next == Some(Token::Word("cmd".into())) {
+        rule.cmd = Some(word(&mut tokens, "a command")?);
+        next = tokens.next();
+        if next == Some(Token::Word("args".into())) {
+            let mut args = Vec::new();
+            for t in tokens.by_ref() {
+                match t {
+                    Token::Word(w) => args.push(w),
+                    _ => return Err("unexpected brace in arguments".into()),
+                }
+            }
+            rule.args = Some(args);
+            next = None;
+        }
+    }
+
+    match next {
+        None => Ok(rule),
+        Some(Token::Word(w)) => Err(format!("unexpected `{}`", w)),
+        Some(_) => Err("unexpected brace".into()),
+    }
+}
+
+/// Convert the contents of a `doas.conf` file read from `path`.
+pub fn convert(contents: &str, path: &Path) -> Conversion {
+    let mut diagnostics = Vec::new();
+    let mut entries = Entries::default();
+
+    let mut report = |severity: Severity, line: usize, message: String| {
+        diagnostics.push(diagnostic(severity, path, line, message));
+    };
+
+    for (line, tokens) in tokenize(contents) {
+        let rule = match tokens.and_then(parse) {
+            Ok(r) => r,
+            Err(e) => {
+                report(Severity::Error, line, e);
+                continue;
+            }
+        };
+
+        if rule.nolog {
+            report(
+                Severity::Warning,
+                line,
+                "`nolog` has no equivalent, as mk does not log commands".into(),
+            );
+        }
+        let (mut keep, mut deny, mut set) = (Vec::new(), Vec::new(), Table::new());
+        if rule.keepenv {
+            keep.push("*".to_owned());
+        }
+        for var in &rule.setenv {
+            if let Some(name) = var.strip_prefix('-') {
+                deny.push(glob::Pattern::escape(name));
+            } else if let Some((name, value)) = var.split_once('=') {
+                if value.starts_with('$') {
+                    report(
+                        Severity::Warning,
+                        line,
+                        format!(
+                            "`{}` copies a variable of the invoker, which has no equivalent; \
+                             variable not set",
+                            var
+                        ),
+                    );
+                } else {
+                    set.insert(name.into(), value.into());
+                }
+            } else {
+                keep.push(glob::Pattern::escape(var));
+            }
+        }
+        if !keep.is_empty() {
+            report(
+                Severity::Warning,
+                line,
+                "variables are only kept when the invoker names them with `-E`".into(),
+            );
+        }
+
+        let target = rule.target.as_deref().map(id_key);
+        let command = match &rule.cmd {
+            Some(c) => match command_pattern(c) {
+                Some(p) => Some(command_rule(p, rule.args.as_deref())),
+                // Dropping a deny rule would permit more than `doas` does
+                None if !rule.permit => {
+                    report(
+                        Severity::Error,
+                        line,
+                        format!("command `{}` of deny rule could not be resolved", c),
+                    );
+                    continue;
+                }
+                None => {
+                    report(
+                        Severity::Warning,
+                        line,
+                        format!("command `{}` could not be resolved; rule skipped", c),
+                    );
+                    continue;
+                }
+            },
+            None => None,
+        };
+
+        let identity = match rule.identity.strip_prefix(':') {
+            Some(g) => Identity::group(g),
+            None => Identity::user(&rule.identity),
+        };
+        let entry = entries.entry(identity, line);
+
+        if rule.permit {
+            let result = entry.permit(Grant {
+                line,
+                targets: target.map(|t| vec![t]),
+                commands: command.map(|c| vec![c]),
+                nopass: rule.nopass,
+                // `doas` remembers authentication for 5 minutes
+                refresh: if rule.persist { Some(5) } else { None },
+                hosts: None,
+                env: env_table(keep, deny, set),
+            });
+            if let Err(e) = result {
+                report(Severity::Warning, line, e);
+            }
+        } else if let Err(e) = entry.deny(target, command) {
+            report(Severity::Error, line, e);
+        }
+    }
+
+    let mut document = Document::default();
+    entries.convert(&mut document, path, &mut diagnostics);
+
+    Conversion {
+        toml: document.render(&format!("Converted from {}", path.display())),
+        diagnostics,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let rules: Vec<_> = tokenize(
+            "# comment\n\
+             permit persist setenv { PATH -HOME } :wheel # trailing\n\
+             deny \"bad user\" as root cmd /bin/sh args -c \\\n  \"echo hi\"\n\
+             permit nopass",
+        )
+        .into_iter()
+        .map(|(line, tokens)| (line, tokens.and_then(parse)))
+        .collect();
+
+        assert_eq!(
+            rules[0],
+            (
+                2,
+                Ok(Rule {
+                    permit: true,
+                    persist: true,
+                    setenv: vec!["PATH".into(), "-HOME".into()],
+                    identity: ":wheel".into(),
+                    ..Rule::default()
+                })
+            )
+        );
+        assert_eq!(
+            rules[1],
+            (
+                3,
+                Ok(Rule {
+                    identity: "bad user".into(),
+                    target: Some("root".into()),
+                    cmd: Some("/bin/sh".into()),
+                    args: Some(vec!["-c".into(), "echo hi".into()]),
+                    ..Rule::default()
+                })
+            )
+        );
+        assert_eq!(rules[2], (5, Err("expected an identity".into())));
+    }
+
+    #[test]
+    fn test_convert() {
+        let conversion = convert(
+            "permit persist setenv { EDITOR PAGER=less -LD_PRELOAD } :wheel\n\
+             permit nopass alice as root cmd /opt/mk/bin/a args x\n\
+             permit nopass alice as root cmd /opt/mk/bin/b\n\
+             permit alice as www-data\n\
+             deny bob\n\
+             permit nolog 1001 as 0\n\
+             deny 1001 cmd /opt/mk/bin/*\n\
+             deny carol as root\n",
+            Path::new("doas.conf"),
+        );
+
+        let lines: Vec<_> = conversion
+            .diagnostics
+            .iter()
+            .map(|d| (d.position.unwrap().0, d.severity))
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                (1, Severity::Warning),
+                (4, Severity::Warning),
+                (6, Severity::Warning),
+                (8, Severity::Error),
+            ]
+        );
+
+        let config = conversion.config().unwrap();
+
+        let wheel = &config.policies["group-wheel"];
+        assert!(wheel.permits.all_targets);
+        assert_eq!(
+            wheel.session.refresh,
+            Some(std::time::Duration::from_secs(300))
+        );
+        assert_eq!(wheel.env.keep, ["EDITOR"]);
+        assert_eq!(wheel.env.deny, ["LD_PRELOAD"]);
+        assert_eq!(wheel.env.set["PAGER"], "less");
+
+        let alice = &config.policies["user-alice"];
+        assert_eq!(alice.permits.targets, ["root"]);
+        assert_eq!(alice.permits.commands.as_ref().unwrap().len(), 2);
+        assert!(alice.session.no_auth);
+
+        assert_eq!(config.policies["user-bob"].permits.commands, Some(vec![]));
+        assert_eq!(config.users["bob"].priority, 5);
+
+        let uid = &config.policies["user-1001"];
+        assert_eq!(uid.permits.targets, ["#0"]);
+        assert_eq!(uid.permits.deny_commands[0].command, "/opt/mk/bin/[*]");
+        assert_eq!(config.users["#1001"].priority, 7);
+
+        assert!(!config.policies.contains_key("user-carol"));
+    }
+
+    #[test]
+    fn test_shadowed() {
+        let conversion = convert(
+            "permit :0\n\
+             permit root cmd /opt/mk/bin/id\n\
+             deny nobody as root cmd /bin/sh\n",
+            Path::new("doas.conf"),
+        );
+
+        let diagnostics: Vec<_> = conversion
+            .diagnostics
+            .iter()
+            .map(|d| (d.position.unwrap().0, d.severity))
+            .collect();
+        assert_eq!(diagnostics, [(3, Severity::Error), (1, Severity::Warning)]);
+        assert!(conversion.diagnostics[1]
+            .message
+            .contains("do not apply to user `root`, whose rules on line 2"));
+    }
+}
@@ -0,0 +1,579 @@
+//! Conversion of other tools' configurations into `mk` configurations.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use indexmap::IndexMap;
+use nix::unistd::{Gid, Group, Uid, User};
+use toml::value::{Table, Value};
+
+use crate::config::Config;
+use crate::diagnostics::{Diagnostic, Severity};
+use crate::prelude::*;
+
+pub mod doas;
+pub mod sudoers;
+
+/// Directories searched for commands given without a path, as with `doas`.
+const SAFE_PATH: &[&str] = &[
+    "/bin",
+    "/sbin",
+    "/usr/bin",
+    "/usr/sbin",
+    "/usr/local/bin",
+    "/usr/local/sbin",
+];
+
+/// Configurations converted from another tool.
+#[derive(Debug)]
+pub struct Conversion {
+    /// The converted configurations, as a TOML document.
+    pub toml: String,
+    /// Parse errors, and constructs that could not be converted.
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Conversion {
+    /// Read the converted configurations.
+    pub fn config(&self) -> Result<Config> {
+        toml::from_str(&self.toml).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e).into())
+    }
+}
+
+/// An `mk` configuration document being built, with tables kept in the order they were added.
+#[derive(Default)]
+struct Document {
+    settings: Table,
+    policies: Table,
+    users: Table,
+    groups: Table,
+}
+
+impl Document {
+    /// Map a user or group key to a policy.
+    fn map(&mut self, group: bool, key: String, policy: &str, priority: Option<i64>) {
+        let mapping = match priority {
+            Some(p) => {
+                let mut t = Table::new();
+                t.insert("policy".into(), policy.into());
+                t.insert("priority".into(), p.into());
+                Value::Table(t)
+            }
+            None => policy.into(),
+        };
+
+        if group {
+            self.groups.insert(key, mapping);
+        } else {
+            self.users.insert(key, mapping);
+        }
+    }
+
+    /// Render the document, with a leading comment.
+    ///
+    /// Each policy is written as one table per section, and mappings are written inline.
+    fn render(self, header: &str) -> String {
+        let mut out = format!("# {}\n", header);
+
+        if !self.settings.is_empty() {
+            out.push('\n');
+            write_entries(&mut out, &self.settings);
+        }
+
+        for (name, policy) in &self.policies {
+            let policy = match policy {
+                Value::Table(t) => t,
+                _ => continue,
+            };
+
+            let (sections, values): (Table, Table) =
+                policy.clone().into_iter().partition(|(_, v)| v.is_table());
+
+            if !values.is_empty() || sections.is_empty() {
+                out.push_str(&format!("\n[policies.{}]\n", key(name)));
+                write_entries(&mut out, &values);
+            }
+            for (section, table) in &sections {
+                out.push_str(&format!("\n[policies.{}.{}]\n", key(name), key(section)));
+                if let Value::Table(t) = table {
+                    write_entries(&mut out, t);
+                }
+            }
+        }
+
+        for (name, mappings) in [("users", &self.users), ("groups", &self.groups)] {
+            if !mappings.is_empty() {
+                out.push_str(&format!("\n[{}]\n", name));
+                write_entries(&mut out, mappings);
+            }
+        }
+
+        out
+    }
+}
+
+/// Format a key, quoting it if needed.
+fn key(k: &str) -> String {
+    let bare = !k.is_empty()
+        && k.bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_');
+
+    if bare {
+        k.to_owned()
+    } else {
+        Value::String(k.to_owned()).to_string()
+    }
+}
+
+/// Format a value on a single line.
+fn inline(value: &Value) -> String {
+    match value {
+        Value::Array(a) => format!("[{}]", a.iter().map(inline).collect::<Vec<_>>().join(", ")),
+        Value::Table(t) => format!(
+            "{{ {} }}",
+            t.iter()
+                .map(|(k, v)| format!("{} = {}", key(k), inline(v)))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        v => v.to_string(),
+    }
+}
+
+/// Write each entry of a table as a `key = value` line.
+fn write_entries(out: &mut String, table: &Table) {
+    for (k, v) in table {
+        out.push_str(&format!("{} = {}\n", key(k), inline(v)));
+    }
+}
+
+/// Create a diagnostic for a line of a file being converted.
+fn diagnostic<S: Into<String>>(
+    severity: Severity,
+    path: &Path,
+    line: usize,
+    message: S,
+) -> Diagnostic {
+    Diagnostic::new(severity, path, Some((line, 1)), message)
+}
+
+/// Create an `env` table, or [`None`] if it would be empty.
+fn env_table(keep: Vec<String>, deny: Vec<String>, set: Table) -> Option<Table> {
+    let mut env = Table::new();
+
+    if !keep.is_empty() {
+        env.insert("keep".into(), keep.into());
+    }
+    if !deny.is_empty() {
+        env.insert("deny".into(), deny.into());
+    }
+    if !set.is_empty() {
+        env.insert("set".into(), Value::Table(set));
+    }
+
+    Some(env).filter(|e| !e.is_empty())
+}
+
+/// Convert a command into a pattern matching its absolute path.
+///
+/// Commands without a `/` are searched for in [`SAFE_PATH`] on this host. Like
+/// [`utils::find_executable`], the directory of the command is canonicalized if it exists. Relative
+/// paths can't be converted.
+fn command_pattern(command: &str) -> Option<String> {
+    let path = if command.starts_with('/') {
+        PathBuf::from(command)
+    } else if command.contains('/') {
+        return None;
+    } else {
+        SAFE_PATH
+            .iter()
+            .map(|d| Path::new(d).join(command))
+            .find(|p| p.is_file())?
+    };
+
+    Some(glob::Pattern::escape(
+        &canonical_command(path).to_string_lossy(),
+    ))
+}
+
+/// Canonicalize the directory of a command's path if it exists, like
+/// [`utils::find_executable`] does.
+fn canonical_command(path: PathBuf) -> PathBuf {
+    match (path.parent().map(fs::canonicalize), path.file_name()) {
+        (Some(Ok(dir)), Some(name)) => dir.join(name),
+        _ => path,
+    }
+}
+
+/// Convert a command and its exact arguments into a command rule.
+fn command_rule(pattern: String, args: Option<&[String]>) -> Value {
+    match args {
+        Some(args) => {
+            let mut t = Table::new();
+            t.insert("command".into(), pattern.into());
+            t.insert(
+                "args".into(),
+                Value::Array(
+                    args.iter()
+                        .map(|a| glob::Pattern::escape(a).into())
+                        .collect(),
+                ),
+            );
+            Value::Table(t)
+        }
+        None => pattern.into(),
+    }
+}
+
+/// Convert a user or group given by name or numeric ID into a mapping key or target.
+fn id_key(id: &str) -> String {
+    if !id.is_empty() && id.bytes().all(|b| b.is_ascii_digit()) {
+        format!("#{}", id)
+    } else {
+        id.to_owned()
+    }
+}
+
+/// A user or group that rules apply to.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Identity {
+    group: bool,
+    /// Mapping key of the user or group.
+    key: String,
+}
+
+impl Identity {
+    /// A user given by name or numeric ID.
+    fn user(id: &str) -> Self {
+        Self {
+            group: false,
+            key: id_key(id),
+        }
+    }
+
+    /// A group given by name or numeric ID.
+    fn group(id: &str) -> Self {
+        Self {
+            group: true,
+            key: id_key(id),
+        }
+    }
+
+    /// All users.
+    fn everyone() -> Self {
+        Self {
+            group: false,
+            key: format!("#0-{}", u32::MAX),
+        }
+    }
+
+    /// Check if this is a user who is a member of a group on this host. All users are members of
+    /// every group, while users and groups that don't exist here are members of none.
+    fn is_member_of(&self, group: &Self) -> bool {
+        if self.group || !group.group {
+            return false;
+        }
+        if *self == Self::everyone() {
+            return true;
+        }
+
+        let user = match self.key.strip_prefix('#') {
+            Some(id) => id
+                .parse()
+                .ok()
+                .map(Uid::from_raw)
+                .and_then(|id| User::from_uid(id).ok()),
+            None => User::from_name(&self.key).ok(),
+        };
+        let gid = match group.key.strip_prefix('#') {
+            Some(id) => id.parse().ok().map(Gid::from_raw),
+            None => Group::from_name(&group.key).ok().flatten().map(|g| g.gid),
+        };
+
+        match (user.flatten(), gid) {
+            (Some(u), Some(gid)) => utils::get_user_groups(&u)
+                .map_or(false, |groups| groups.iter().any(|g| g.gid == gid)),
+            _ => false,
+        }
+    }
+
+    /// Name of the policy converted for this identity.
+    fn policy(&self) -> String {
+        if *self == Self::everyone() {
+            return "all-users".into();
+        }
+
+        let kind = if self.group { "group" } else { "user" };
+        format!("{}-{}", kind, self.key.trim_start_matches('#'))
+    }
+}
+
+impl std::fmt::Display for Identity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = if self.group { "group" } else { "user" };
+        write!(f, "{} `{}`", kind, self.key)
+    }
+}
+
+/// Permissions granted to an identity by rules that could be combined.
+#[derive(Debug)]
+struct Grant {
+    /// Line of the first rule.
+    line: usize,
+    /// Permitted targets, or [`None`] for all targets.
+    targets: Option<Vec<String>>,
+    /// Permitted command rules, or [`None`] for all commands.
+    commands: Option<Vec<Value>>,
+    nopass: bool,
+    /// Session refresh timeout in minutes.
+    refresh: Option<i64>,
+    /// Host name patterns, or [`None`] for all hosts.
+    hosts: Option<Vec<String>>,
+    /// Environment rules, or [`None`] for the default environment.
+    env: Option<Table>,
+}
+
+/// Combine two lists, where [`None`] stands for everything.
+fn union<T: PartialEq>(a: Option<Vec<T>>, b: Option<Vec<T>>) -> Option<Vec<T>> {
+    match (a, b) {
+        (Some(mut a), Some(b)) => {
+            for x in b {
+                if !a.contains(&x) {
+                    a.push(x);
+                }
+            }
+            Some(a)
+        }
+        _ => None,
+    }
+}
+
+impl Grant {
+    /// Combine another grant into this one, if the result permits exactly what both do.
+    fn combine(&mut self, other: Self) -> bool {
+        if self.nopass != other.nopass
+            || self.refresh != other.refresh
+            || self.hosts != other.hosts
+            || self.env != other.env
+        {
+            return false;
+        }
+
+        if self.targets == other.targets {
+            self.commands = union(self.commands.take(), other.commands);
+        } else if self.commands == other.commands {
+            self.targets = union(self.targets.take(), other.targets);
+        } else {
+            return false;
+        }
+
+        true
+    }
+}
+
+/// Everything the rules naming one identity convert to.
+#[derive(Debug, Default)]
+struct Entry {
+    /// Line of the first rule naming this identity.
+    line: usize,
+    /// Position of the last rule naming this identity among all rules.
+    last: usize,
+    grant: Option<Grant>,
+    /// Whether a rule denied everything, and no rule permitted anything since.
+    denied: bool,
+    deny_targets: Vec<String>,
+    deny_commands: Vec<Value>,
+}
+
+impl Entry {
+    /// Add a rule permitting a grant.
+    ///
+    /// # Errors
+    ///
+    /// Returns a description of why the rule was not converted exactly.
+    fn permit(&mut self, grant: Grant) -> core::result::Result<(), String> {
+        let mut result = Ok(());
+
+        if !self.deny_targets.is_empty() || !self.deny_commands.is_empty() {
+            result =
+                Err("earlier deny rules for this identity take precedence over this rule".into());
+        }
+
+        match &mut self.grant {
+            Some(g) => {
+                let first = g.line;
+                if !g.combine(grant) {
+                    return Err(format!(
+                        "rule cannot be combined with the rule on line {}; rule skipped",
+                        first
+                    ));
+                }
+            }
+            None => {
+                self.grant = Some(grant);
+                self.denied = false;
+            }
+        }
+
+        result
+    }
+
+    /// Add a rule denying a target, a command, or everything if neither is given.
+    ///
+    /// # Errors
+    ///
+    /// Returns a description of why the rule was not converted.
+    fn deny(
+        &mut self,
+        target: Option<String>,
+        command: Option<Value>,
+    ) -> core::result::Result<(), String> {
+        match (target, command) {
+            // Everything permitted earlier is overridden
+            (None, None) => {
+                self.grant = None;
+                self.denied = true;
+                self.deny_targets.clear();
+                self.deny_commands.clear();
+            }
+            (Some(t), None) => self.deny_targets.push(t),
+            (None, Some(c)) => self.deny_commands.push(c),
+            (Some(_), Some(_)) => {
+                return Err(
+                    "deny rules restricting both a target and a command cannot be expressed".into(),
+                )
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Converted rules for each identity, in the order identities were first named.
+#[derive(Debug, Default)]
+struct Entries {
+    entries: IndexMap<Identity, Entry>,
+    /// Number of rules seen so far.
+    rules: usize,
+}
+
+impl Entries {
+    /// Get the entry of an identity for a rule on a line.
+    ///
+    /// As rules are used in order, with the last matching rule winning, the entry's mapping will
+    /// have precedence over those of all identities named before.
+    fn entry(&mut self, identity: Identity, line: usize) -> &mut Entry {
+        self.rules += 1;
+
+        let entry = self.entries.entry(identity).or_insert_with(|| Entry {
+            line,
+            ..Entry::default()
+        });
+        entry.last = self.rules;
+        entry
+    }
+
+    /// Add a policy and mapping for each identity to a document.
+    ///
+    /// Deny rules that can't restrict what other rules permit are errors, since dropping them
+    /// would permit more than the original rules did. As only one policy applies to each user,
+    /// the rules of users that shadow those of their groups, or the other way around, are
+    /// reported.
+    fn convert(self, document: &mut Document, path: &Path, diagnostics: &mut Vec<Diagnostic>) {
+        let grants: Vec<_> = self
+            .entries
+            .iter()
+            .filter(|(_, e)| e.grant.is_some())
+            .collect();
+        for (user, u) in grants.iter().filter(|(i, _)| !i.group) {
+            for (group, g) in grants.iter().filter(|(i, _)| user.is_member_of(i)) {
+                let (line, message) = if u.last > g.last {
+                    (
+                        g.line,
+                        format!(
+                            "rules for {} do not apply to {}, whose rules on line {} take \
+                             precedence",
+                            group, user, u.line
+                        ),
+                    )
+                } else {
+                    (
+                        u.line,
+                        format!(
+                            "rules for {} do not apply if they are a member of {}, whose rules \
+                             on line {} take precedence",
+                            user, group, g.line
+                        ),
+                    )
+                };
+
+                // Only one policy applies to each user
+                diagnostics.push(diagnostic(Severity::Warning, path, line, message));
+            }
+        }
+
+        for (identity, entry) in self.entries {
+            let mut policy = Table::new();
+            let mut permits = Table::new();
+            let mut session = Table::new();
+
+            match entry.grant {
+                Some(g) => {
+                    match g.targets {
+                        Some(t) => permits.insert("targets".into(), t.into()),
+                        None => permits.insert("all-targets".into(), true.into()),
+                    };
+                    if let Some(c) = g.commands {
+                        permits.insert("commands".into(), c.into());
+                    }
+                    if g.nopass {
+                        session.insert("no-auth".into(), true.into());
+                    } else if let Some(r) = g.refresh {
+                        session.insert("refresh".into(), r.into());
+                    }
+                    if let Some(h) = g.hosts {
+                        policy.insert("hosts".into(), h.into());
+                    }
+                    if let Some(e) = g.env {
+                        policy.insert("env".into(), Value::Table(e));
+                    }
+                }
+                // Override any policy of lower priority
+                None if entry.denied => {
+                    permits.insert("commands".into(), Value::Array(Vec::new()));
+                }
+                // Every rule was skipped, and already reported
+                None if entry.deny_targets.is_empty() && entry.deny_commands.is_empty() => continue,
+                None => {
+                    diagnostics.push(diagnostic(
+                        Severity::Error,
+                        path,
+                        entry.line,
+                        format!(
+                            "deny rules for {} cannot restrict what other rules permit",
+                            identity
+                        ),
+                    ));
+                    continue;
+                }
+            }
+
+            if !entry.deny_targets.is_empty() {
+                permits.insert("deny-targets".into(), entry.deny_targets.into());
+            }
+            if !entry.deny_commands.is_empty() {
+                permits.insert("deny-commands".into(), entry.deny_commands.into());
+            }
+
+            policy.insert("permits".into(), Value::Table(permits));
+            if !session.is_empty() {
+                policy.insert("session".into(), Value::Table(session));
+            }
+
+            let name = identity.policy();
+            document.policies.insert(name.clone(), Value::Table(policy));
+            document.map(identity.group, identity.key, &name, Some(entry.last as i64));
+        }
+    }
+}
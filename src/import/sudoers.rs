@@ -0,0 +1,862 @@
+//! Conversion of a practical subset of [`sudoers`](https://www.sudo.ws/docs/man/sudoers.man/).
+//!
+//! User specifications, `Runas` lists, the `NOPASSWD` and `NOEXEC` tags, aliases and the global
+//! `timestamp_timeout` default are converted. As with [`super::doas`], each user or group gets its
+//! own policy, and mappings are given priorities in the order their identities were last named,
+//! since `sudo` uses the last matching rule. Anything else is reported, and specifications that
+//! cannot be converted exactly are skipped.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use toml::value::{Table, Value};
+
+use super::*;
+
+/// Path of the `sudo` configuration file.
+pub const PATH: &str = "/etc/sudoers";
+
+/// Default time in minutes for which `sudo` remembers authentication.
+const DEFAULT_TIMEOUT: i64 = 5;
+
+/// Tags that restrict commands in ways `mk` can't. Commands with these tags are skipped.
+const RESTRICTIVE_TAGS: &[&str] = &["INTERCEPT"];
+
+/// Tags that have no equivalent, but don't restrict commands.
+const IGNORED_TAGS: &[&str] = &[
+    "NOINTERCEPT",
+    "SETENV",
+    "NOSETENV",
+    "LOG_INPUT",
+    "NOLOG_INPUT",
+    "LOG_OUTPUT",
+    "NOLOG_OUTPUT",
+    "MAIL",
+    "NOMAIL",
+    "FOLLOW",
+    "NOFOLLOW",
+];
+
+/// Options that restrict how commands are run. Commands with these options are skipped.
+const OPTIONS: &[&str] = &[
+    "ROLE",
+    "TYPE",
+    "CWD",
+    "CHROOT",
+    "TIMEOUT",
+    "NOTBEFORE",
+    "NOTAFTER",
+    "APPARMOR_PROFILE",
+    "PRIVS",
+    "LIMITPRIVS",
+];
+
+/// Prefixes of command digests.
+const DIGESTS: &[&str] = &["sha224:", "sha256:", "sha384:", "sha512:"];
+
+/// Kinds of aliases.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum AliasKind {
+    User,
+    Runas,
+    Host,
+    Cmnd,
+}
+
+/// Split `s` at each `sep` that isn't escaped, quoted or in parentheses. Escapes are kept.
+fn split(s: &str, sep: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let (mut start, mut escaped, mut quoted, mut depth) = (0, false, false, 0);
+
+    for (i, c) in s.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+
+        match c {
+            '\\' => escaped = true,
+            '"' => quoted = !quoted,
+            '(' if !quoted => depth += 1,
+            ')' if !quoted && depth > 0 => depth -= 1,
+            c if c == sep && !quoted && depth == 0 => {
+                parts.push(&s[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+
+    parts.push(&s[start..]);
+    parts
+}
+
+/// Remove backslash escapes and quotes.
+fn unescape(s: &str) -> String {
+    let mut out = String::new();
+    let mut chars = s.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => out.extend(chars.next()),
+            '"' => {}
+            c => out.push(c),
+        }
+    }
+
+    out
+}
+
+/// Remove a trailing comment from a line. A `#` followed by a digit is a numeric ID instead.
+fn strip_comment(line: &str) -> &str {
+    if line.trim_start().starts_with("#include") {
+        return line;
+    }
+
+    let (mut escaped, mut quoted) = (false, false);
+
+    for (i, c) in line.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+
+        match c {
+            '\\' => escaped = true,
+            '"' => quoted = !quoted,
+            '#' if !quoted && !line[i + 1..].starts_with(|c: char| c.is_ascii_digit()) => {
+                return &line[..i]
+            }
+            _ => {}
+        }
+    }
+
+    line
+}
+
+/// Join continued lines and remove comments, returning each non-empty logical line along with the
+/// line it starts on.
+fn logical_lines(contents: &str) -> Vec<(usize, String)> {
+    let mut lines = Vec::new();
+    let mut current: Option<(usize, String)> = None;
+
+    for (n, line) in contents.lines().enumerate() {
+        let (start, mut text) = current.take().unwrap_or_else(|| (n + 1, String::new()));
+        text.push_str(line);
+
+        let backslashes = text.chars().rev().take_while(|c| *c == '\\').count();
+        if backslashes % 2 == 1 {
+            text.pop();
+            current = Some((start, text));
+            continue;
+        }
+
+        let text = strip_comment(&text).trim();
+        if !text.is_empty() {
+            lines.push((start, text.to_owned()));
+        }
+    }
+
+    if let Some((start, text)) = current {
+        let text = strip_comment(&text).trim();
+        if !text.is_empty() {
+            lines.push((start, text.to_owned()));
+        }
+    }
+
+    lines
+}
+
+/// Check if a list item names an alias.
+fn is_alias(item: &str) -> bool {
+    item != "ALL"
+        && item.starts_with(|c: char| c.is_ascii_uppercase())
+        && item
+            .bytes()
+            .all(|b| b.is_ascii_uppercase() || b.is_ascii_digit() || b == b'_')
+}
+
+/// Check if a pattern contains wildcards.
+fn has_wildcards(s: &str) -> bool {
+    s.contains(|c| matches!(c, '*' | '?' | '['))
+}
+
+/// Permissions of a user specification that share a `Runas` list and tags.
+struct Group {
+    targets: Option<Vec<String>>,
+    nopass: bool,
+    commands: Option<Vec<Value>>,
+}
+
+/// State of a conversion.
+struct Converter<'a> {
+    path: &'a Path,
+    aliases: HashMap<(AliasKind, String), Vec<String>>,
+    /// Authentication timeout in minutes, or [`None`] to authenticate every time.
+    timeout: Option<i64>,
+    /// Patterns of variables that may be kept.
+    env_keep: Vec<String>,
+    /// Patterns of variables that are never kept.
+    env_delete: Vec<String>,
+    entries: Entries,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Converter<'a> {
+    fn report<S: Into<String>>(&mut self, severity: Severity, line: usize, message: S) {
+        self.diagnostics
+            .push(diagnostic(severity, self.path, line, message));
+    }
+
+    /// Convert `Defaults` parameters.
+    fn defaults(&mut self, line: usize, params: &str) {
+        for param in split(params, ',') {
+            let param = param.trim();
+            let (name, operator, value) = match param.split_once('=') {
+                Some((n, v)) => {
+                    let n = n.trim_end();
+                    let name = n.trim_end_matches(&['+', '-'][..]).trim_end();
+                    (name, n[name.len()..].chars().next(), Some(unescape(v)))
+                }
+                None => (param, None, None),
+            };
+
+            match (name, value) {
+                ("timestamp_timeout", Some(v)) => match v.trim().parse::<f64>() {
+                    Ok(m) if m < 0.0 => {
+                        self.timeout = None;
+                        self.report(
+                            Severity::Warning,
+                            line,
+                            "authentication that never expires has no equivalent; users will \
+                             authenticate every time",
+                        );
+                    }
+                    // Partial minutes are rounded down
+                    Ok(m) => self.timeout = Some(m as i64).filter(|m| *m > 0),
+                    Err(_) => self.report(
+                        Severity::Error,
+                        line,
+                        format!("invalid timestamp_timeout `{}`", v),
+                    ),
+                },
+                // mk always resets the environment
+                ("env_reset", None) => {}
+                ("env_keep", Some(v)) | ("env_delete", Some(v)) => {
+                    let vars: Vec<String> = v.split_whitespace().map(str::to_owned).collect();
+                    let list = if name == "env_keep" {
+                        &mut self.env_keep
+                    } else {
+                        &mut self.env_delete
+                    };
+
+                    match operator {
+                        Some('+') => list.extend(vars),
+                        Some('-') => list.retain(|v| !vars.contains(v)),
+                        _ => *list = vars,
+                    }
+
+                    if name == "env_keep" {
+                        self.report(
+                            Severity::Warning,
+                            line,
+                            "variables in `env_keep` are only kept when the invoker names them \
+                             with `-E`",
+                        );
+                    }
+                }
+                _ => self.report(
+                    Severity::Warning,
+                    line,
+                    format!("`Defaults {}` has no equivalent; ignored", param),
+                ),
+            }
+        }
+    }
+
+    /// Record alias definitions.
+    fn alias(&mut self, kind: AliasKind, line: usize, definitions: &str) {
+        for definition in split(definitions, ':') {
+            match definition.split_once('=') {
+                Some((name, items)) if is_alias(name.trim()) => {
+                    let items = split(items, ',')
+                        .into_iter()
+                        .map(|i| i.trim().to_owned())
+                        .collect();
+                    self.aliases.insert((kind, name.trim().to_owned()), items);
+                }
+                _ => self.report(
+                    Severity::Error,
+                    line,
+                    format!("invalid alias definition `{}`", definition.trim()),
+                ),
+            }
+        }
+    }
+
+    /// Expand aliases in a list, returning each item along with whether it is negated.
+    fn expand<S: AsRef<str>>(
+        &self,
+        kind: AliasKind,
+        items: &[S],
+    ) -> core::result::Result<Vec<(bool, String)>, String> {
+        let mut expanded = Vec::new();
+        self.expand_into(kind, items, false, 0, &mut expanded)?;
+        Ok(expanded)
+    }
+
+    fn expand_into<S: AsRef<str>>(
+        &self,
+        kind: AliasKind,
+        items: &[S],
+        negated: bool,
+        depth: usize,
+        expanded: &mut Vec<(bool, String)>,
+    ) -> core::result::Result<(), String> {
+        if depth > 16 {
+            return Err("aliases are nested too deeply".into());
+        }
+
+        for item in items {
+            let mut item = item.as_ref().trim();
+            let mut negated = negated;
+
+            while let Some(rest) = item.strip_prefix('!') {
+                negated = !negated;
+                item = rest.trim_start();
+            }
+
+            if is_alias(item) {
+                match self.aliases.get(&(kind, item.to_owned())) {
+                    Some(a) => self.expand_into(kind, a, negated, depth + 1, expanded)?,
+                    None => return Err(format!("undefined alias `{}`", item)),
+                }
+            } else {
+                expanded.push((negated, item.to_owned()));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Convert a `Runas` list into permitted targets, or [`None`] for all targets, and denied
+    /// targets.
+    fn runas(
+        &mut self,
+        line: usize,
+        spec: &str,
+    ) -> core::result::Result<(Option<Vec<String>>, Vec<String>), String> {
+        let (users, groups) = match spec.split_once(':') {
+            Some((u, g)) => (u.trim(), g.trim()),
+            None => (spec.trim(), ""),
+        };
+
+        if users.is_empty() {
+            return Err("running commands as a group only is not supported".into());
+        }
+        if !groups.is_empty() {
+            self.report(
+                Severity::Warning,
+                line,
+                "groups in `Runas` lists are not supported; commands run with the target's \
+                 primary and supplementary groups",
+            );
+        }
+
+        let (mut targets, mut denied, mut all) = (Vec::new(), Vec::new(), false);
+
+        for (negated, item) in self.expand(AliasKind::Runas, &split(users, ','))? {
+            let target = if item == "ALL" {
+                all = !negated;
+                continue;
+            } else if item.starts_with("%#") || item.starts_with("%:") || item.starts_with('+') {
+                return Err(format!("`Runas` item `{}` is not supported", item));
+            } else if let Some(uid) = item.strip_prefix('#') {
+                id_key(uid)
+            } else {
+                unescape(&item)
+            };
+
+            if negated {
+                denied.push(target);
+            } else {
+                targets.push(target);
+            }
+        }
+
+        Ok((if all { None } else { Some(targets) }, denied))
+    }
+
+    /// Convert a command into a command rule, or [`None`] for all commands.
+    fn command(
+        &mut self,
+        line: usize,
+        command: &str,
+    ) -> core::result::Result<Option<Value>, String> {
+        if command == "ALL" {
+            return Ok(None);
+        }
+        if DIGESTS.iter().any(|d| command.starts_with(d)) {
+            return Err("command digests are not supported".into());
+        }
+
+        let (path, args) = match command.split_once(char::is_whitespace) {
+            Some((p, a)) => (unescape(p), Some(a.trim())),
+            None => (unescape(command), None),
+        };
+
+        if path == "sudoedit" {
+            return Err("`sudoedit` is not supported".into());
+        }
+        if !path.starts_with('/') {
+            return Err(format!("command `{}` is not an absolute path", path));
+        }
+
+        let pattern = if has_wildcards(&path) {
+            path
+        } else if let Some(dir) = path.strip_suffix('/') {
+            // All files in a directory
+            let dir = fs::canonicalize(dir).unwrap_or_else(|_| PathBuf::from(dir));
+            format!("{}/*", glob::Pattern::escape(&dir.to_string_lossy()))
+        } else {
+            glob::Pattern::escape(&canonical_command(PathBuf::from(path)).to_string_lossy())
+        };
+
+        let args = match args {
+            None => return Ok(Some(pattern.into())),
+            Some("\"\"") => Vec::new(),
+            Some(a) => a.split_whitespace().map(unescape).collect(),
+        };
+
+        let mut rule = Table::new();
+        rule.insert("command".into(), pattern.into());
+
+        match args.split_last() {
+            Some((last, prefix)) if last == "*" => {
+                rule.insert("args-prefix".into(), prefix.to_vec().into());
+            }
+            _ => {
+                rule.insert("args".into(), args.clone().into());
+            }
+        }
+
+        if args.iter().any(|a| a != "*" && has_wildcards(a)) {
+            self.report(
+                Severity::Warning,
+                line,
+                format!(
+                    "wildcards in the arguments of `{}` only match single arguments in mk",
+                    command
+                ),
+            );
+        }
+
+        Ok(Some(Value::Table(rule)))
+    }
+
+    /// Convert the users of a user specification.
+    fn identities(&self, users: &[&str]) -> core::result::Result<Vec<Identity>, String> {
+        self.expand(AliasKind::User, users)?
+            .into_iter()
+            .map(|(negated, item)| {
+                if negated {
+                    return Err("negated users are not supported".into());
+                }
+
+                Ok(if item == "ALL" {
+                    Identity::everyone()
+                } else if let Some(gid) = item.strip_prefix("%#") {
+                    Identity::group(gid)
+                } else if item.starts_with("%:") || item.starts_with('+') {
+                    return Err(format!("user `{}` is not supported", item));
+                } else if let Some(group) = item.strip_prefix('%') {
+                    Identity::group(&unescape(group))
+                } else if let Some(uid) = item.strip_prefix('#') {
+                    Identity::user(uid)
+                } else {
+                    Identity::user(&unescape(&item))
+                })
+            })
+            .collect()
+    }
+
+    /// Convert a host list into host name patterns, or [`None`] for all hosts.
+    fn hosts(&self, hosts: &[&str]) -> core::result::Result<Option<Vec<String>>, String> {
+        let mut patterns = Vec::new();
+
+        for (negated, item) in self.expand(AliasKind::Host, hosts)? {
+            if negated
+                || item.starts_with('+')
+                || item.contains('/')
+                || item.parse::<std::net::IpAddr>().is_ok()
+            {
+                return Err(format!("host `{}` is not supported", item));
+            }
+            if item == "ALL" {
+                return Ok(None);
+            }
+            patterns.push(unescape(&item));
+        }
+
+        Ok(Some(patterns))
+    }
+
+    /// Convert a user specification.
+    fn spec(&mut self, line: usize, spec: &str) -> core::result::Result<(), String> {
+        let left = split(spec, '=')[0];
+        let right = match spec.get(left.len() + 1..) {
+            Some(r) => r,
+            None => return Err(format!("expected `=` in `{}`", spec)),
+        };
+
+        // Users are separated from hosts by whitespace
+        let (mut users, mut hosts) = (Vec::new(), Vec::new());
+        for item in split(left, ',') {
+            let item = item.trim();
+            if !hosts.is_empty() {
+                hosts.push(item);
+            } else if let Some((u, h)) = item.split_once(char::is_whitespace) {
+                users.push(u);
+                hosts.push(h.trim());
+            } else {
+                users.push(item);
+            }
+        }
+        if hosts.is_empty() {
+            return Err("expected a host list".into());
+        }
+
+        let identities = self.identities(&users)?;
+        let hosts = self.hosts(&hosts)?;
+
+        let mut groups: Vec<Group> = Vec::new();
+        let mut targets = Some(vec!["root".to_owned()]);
+        let (mut deny_targets, mut deny_commands) = (Vec::new(), Vec::new());
+        let (mut nopass, mut noexec) = (false, false);
+
+        for item in split(right, ',') {
+            let mut item = item.trim();
+
+            if let Some(rest) = item.strip_prefix('(') {
+                let end = rest.find(')').ok_or("unterminated `Runas` list")?;
+                let (t, d) = self.runas(line, &rest[..end])?;
+                targets = t;
+                deny_targets.extend(d);
+                item = rest[end + 1..].trim_start();
+            }
+
+            let mut skip = None;
+
+            while let Some((word, rest)) = item.split_once(|c| c == ':' || c == '=') {
+                let word = word.trim();
+                let option = OPTIONS.contains(&word) && item[word.len()..].starts_with('=');
+
+                if option {
+                    skip = Some(format!("option `{}` is not supported", word));
+                    item = rest.split_once(char::is_whitespace).map_or("", |(_, r)| r);
+                } else if word == "NOPASSWD" || word == "PASSWD" {
+                    nopass = word == "NOPASSWD";
+                    item = rest;
+                } else if word == "NOEXEC" || word == "EXEC" {
+                    noexec = word == "NOEXEC";
+                    item = rest;
+                } else if RESTRICTIVE_TAGS.contains(&word) {
+                    skip = Some(format!("tag `{}` is not supported", word));
+                    item = rest;
+                } else if IGNORED_TAGS.contains(&word) {
+                    self.report(
+                        Severity::Warning,
+                        line,
+                        format!("tag `{}` has no equivalent; ignored", word),
+                    );
+                    item = rest;
+                } else {
+                    break;
+                }
+
+                item = item.trim_start();
+            }
+
+            let digest = DIGESTS.iter().any(|d| item.starts_with(d));
+            if !digest && split(item, ':').len() > 1 {
+                return Err("multiple host lists in one specification are not supported".into());
+            }
+
+            for (negated, command) in self.expand(AliasKind::Cmnd, &[item])? {
+                // Dropping a denied command would permit more than `sudo` does
+                if negated {
+                    if let Some(reason) = skip {
+                        return Err(format!("{} for denied command `{}`", reason, command));
+                    }
+                    match self.command(line, &command)? {
+                        Some(r) => deny_commands.push(r),
+                        None => return Err("`!ALL` is not supported".into()),
+                    }
+                    continue;
+                }
+
+                if let Some(reason) = &skip {
+                    self.report(
+                        Severity::Warning,
+                        line,
+                        format!("{}; command `{}` skipped", reason, command),
+                    );
+                    continue;
+                }
+
+                let rule = match self.command(line, &command) {
+                    Ok(r) => r,
+                    Err(e) => {
+                        self.report(Severity::Warning, line, format!("{}; command skipped", e));
+                        continue;
+                    }
+                };
+
+                let rule = match rule {
+                    Some(r) if noexec => Some(with_noexec(r)),
+                    None if noexec => {
+                        self.report(
+                            Severity::Warning,
+                            line,
+                            "tag `NOEXEC` is not supported with `ALL`; command `ALL` skipped",
+                        );
+                        continue;
+                    }
+                    r => r,
+                };
+
+                match groups.last_mut() {
+                    Some(g) if g.targets == targets && g.nopass == nopass => {
+                        g.commands = match (g.commands.take(), rule) {
+                            (Some(mut c), Some(r)) => {
+                                c.push(r);
+                                Some(c)
+                            }
+                            _ => None,
+                        }
+                    }
+                    _ => groups.push(Group {
+                        targets: targets.clone(),
+                        nopass,
+                        commands: rule.map(|r| vec![r]),
+                    }),
+                }
+            }
+        }
+
+        // Everything was skipped
+        if groups.is_empty() && deny_targets.is_empty() && deny_commands.is_empty() {
+            return Ok(());
+        }
+
+        let env = if self.env_keep.is_empty() {
+            None
+        } else {
+            env_table(self.env_keep.clone(), self.env_delete.clone(), Table::new())
+        };
+
+        for identity in identities {
+            let entry = self.entries.entry(identity, line);
+            let mut errors = Vec::new();
+
+            for group in &groups {
+                let grant = Grant {
+                    line,
+                    targets: group.targets.clone(),
+                    commands: group.commands.clone(),
+                    nopass: group.nopass,
+                    refresh: if group.nopass { None } else { self.timeout },
+                    hosts: hosts.clone(),
+                    env: env.clone(),
+                };
+                errors.extend(entry.permit(grant).err());
+            }
+
+            for t in &deny_targets {
+                errors.extend(entry.deny(Some(t.clone()), None).err());
+            }
+            for c in &deny_commands {
+                errors.extend(entry.deny(None, Some(c.clone())).err());
+            }
+
+            for e in errors {
+                self.report(Severity::Warning, line, e);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Set `noexec` on a command rule.
+fn with_noexec(rule: Value) -> Value {
+    let mut table = match rule {
+        Value::Table(t) => t,
+        command => {
+            let mut t = Table::new();
+            t.insert("command".into(), command);
+            t
+        }
+    };
+    table.insert("noexec".into(), true.into());
+    Value::Table(table)
+}
+
+/// Convert the contents of a `sudoers` file read from `path`.
+pub fn convert(contents: &str, path: &Path) -> Conversion {
+    let mut converter = Converter {
+        path,
+        aliases: HashMap::new(),
+        timeout: Some(DEFAULT_TIMEOUT),
+        env_keep: Vec::new(),
+        env_delete: Vec::new(),
+        entries: Entries::default(),
+        diagnostics: Vec::new(),
+    };
+
+    // Defaults and aliases apply to all user specifications, so they are read first
+    let mut specs = Vec::new();
+
+    for (line, text) in logical_lines(contents) {
+        let (word, rest) = match text.split_once(char::is_whitespace) {
+            Some((w, r)) => (w, r.trim()),
+            None => (&text[..], ""),
+        };
+
+        match word {
+            "Defaults" => converter.defaults(line, rest),
+            w if w.starts_with("Defaults") => converter.report(
+                Severity::Warning,
+                line,
+                "`Defaults` for specific users, hosts or commands are not supported; line skipped",
+            ),
+            "User_Alias" => converter.alias(AliasKind::User, line, rest),
+            "Runas_Alias" => converter.alias(AliasKind::Runas, line, rest),
+            "Host_Alias" => converter.alias(AliasKind::Host, line, rest),
+            "Cmnd_Alias" | "Cmd_Alias" => converter.alias(AliasKind::Cmnd, line, rest),
+            "#include" | "#includedir" | "@include" | "@includedir" => converter.report(
+                Severity::Warning,
+                line,
+                "includes are not supported; convert included files separately",
+            ),
+            _ => specs.push((line, text)),
+        }
+    }
+
+    for (line, spec) in specs {
+        if let Err(e) = converter.spec(line, &spec) {
+            converter.report(
+                Severity::Warning,
+                line,
+                format!("{}; specification skipped", e),
+            );
+        }
+    }
+
+    let mut document = Document::default();
+    let mut diagnostics = converter.diagnostics;
+    converter
+        .entries
+        .convert(&mut document, path, &mut diagnostics);
+    diagnostics.sort_by_key(|d| d.position);
+
+    Conversion {
+        toml: document.render(&format!("Converted from {}", path.display())),
+        diagnostics,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lines() {
+        let lines = logical_lines(
+            "# comment\n\
+             alice ALL = /bin/a, \\\n    /bin/b # trailing\n\
+             #1000 ALL = ALL\n\
+             #includedir /etc/sudoers.d\n",
+        );
+
+        assert_eq!(
+            lines,
+            [
+                (2, "alice ALL = /bin/a,     /bin/b".to_owned()),
+                (4, "#1000 ALL = ALL".to_owned()),
+                (5, "#includedir /etc/sudoers.d".to_owned()),
+            ]
+        );
+        assert_eq!(split("a\\,b, (c, d) e", ','), ["a\\,b", " (c, d) e"]);
+    }
+
+    #[test]
+    fn test_convert() {
+        let conversion = convert(
+            "Defaults timestamp_timeout=2.5\n\
+             Defaults env_keep += \"LANG\"\n\
+             User_Alias OPS = alice, %#100\n\
+             Cmnd_Alias LOGS = /opt/mk/bin/logs *, /opt/mk/libexec/\n\
+             OPS db-* = (postgres, %dba) NOPASSWD: LOGS\n\
+             bob ALL = ALL, !/opt/mk/bin/sh, !/opt/mk/bin/sh]\n\
+             carol ALL = (ALL, !root) /opt/mk/bin/id \"\"\n\
+             dave ALL = (root) CWD=/tmp /opt/mk/bin/x\n\
+             !eve ALL = ALL\n\
+             frank ALL = NOEXEC: /opt/mk/bin/vi, /opt/mk/bin/less, EXEC: /opt/mk/bin/id\n\
+             grace ALL = ALL, !sudoedit /etc/shadow\n\
+             heidi ALL = ALL, INTERCEPT: !/opt/mk/bin/sh\n",
+            Path::new("sudoers"),
+        );
+
+        let lines: Vec<_> = conversion
+            .diagnostics
+            .iter()
+            .map(|d| d.position.unwrap().0)
+            .collect();
+        assert_eq!(lines, [2, 8, 9, 11, 12]);
+
+        let config = conversion.config().unwrap();
+
+        let ops = &config.policies["user-alice"];
+        assert_eq!(ops.permits.targets, ["postgres", "%dba"]);
+        assert_eq!(ops.hosts, Some(vec!["db-*".to_owned()]));
+        assert!(ops.session.no_auth);
+        let commands = ops.permits.commands.as_ref().unwrap();
+        assert_eq!(commands[0].args_prefix, Some(vec![]));
+        assert_eq!(commands[1].command, "/opt/mk/libexec/*");
+        assert_eq!(config.groups["#100"].policy, "group-100");
+
+        let bob = &config.policies["user-bob"];
+        assert_eq!(bob.permits.targets, ["root"]);
+        assert_eq!(bob.permits.commands, None);
+        assert_eq!(bob.permits.deny_commands[0].command, "/opt/mk/bin/sh");
+        assert_eq!(bob.permits.deny_commands[1].command, "/opt/mk/bin/sh[]]");
+        assert_eq!(bob.env.keep, ["LANG"]);
+        assert_eq!(
+            bob.session.refresh,
+            Some(std::time::Duration::from_secs(120))
+        );
+
+        let carol = &config.policies["user-carol"];
+        assert!(carol.permits.all_targets);
+        assert_eq!(carol.permits.deny_targets, ["root"]);
+        assert_eq!(
+            carol.permits.commands.as_ref().unwrap()[0].args,
+            Some(vec![])
+        );
+
+        assert!(!config.policies.contains_key("user-dave"));
+        assert!(!config.users.contains_key("eve"));
+        // Specifications whose denied commands can't be converted are skipped entirely
+        assert!(!config.users.contains_key("grace"));
+        assert!(!config.users.contains_key("heidi"));
+
+        let frank = config.policies["user-frank"]
+            .permits
+            .commands
+            .as_ref()
+            .unwrap();
+        assert!(frank[0].noexec && frank[1].noexec);
+        assert!(!frank[2].noexec);
+    }
+}
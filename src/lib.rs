@@ -10,13 +10,23 @@
 pub mod utils;
 
 pub mod auth;
+pub mod capabilities;
 pub mod cli;
 pub mod config;
+pub mod decision;
+pub mod diagnostics;
+pub mod env;
 pub mod errors;
+pub mod import;
+pub mod mapping;
+pub mod noexec;
 pub mod options;
+pub mod origin;
 pub mod permits;
 pub mod policy;
 pub mod prelude;
+pub mod process;
+pub mod schedule;
 pub mod session;
 
 pub use errors::*;
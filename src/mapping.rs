@@ -0,0 +1,212 @@
+//! User and group policy mappings.
+
+use nix::unistd::{Group, User};
+
+use crate::prelude::*;
+
+mod defaults {
+    #[inline]
+    pub const fn hosts() -> Option<Vec<String>> {
+        None
+    }
+
+    #[inline]
+    pub const fn priority() -> i64 {
+        0
+    }
+}
+
+/// A user or group, as matched by the keys of mappings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Subject {
+    /// User or group name.
+    pub name: String,
+    /// User or group ID.
+    pub id: u32,
+}
+
+impl Subject {
+    #[must_use]
+    pub fn new<S: Into<String>>(name: S, id: u32) -> Self {
+        Self {
+            name: name.into(),
+            id,
+        }
+    }
+}
+
+impl From<&User> for Subject {
+    fn from(u: &User) -> Self {
+        Self::new(&u.name[..], u.uid.as_raw())
+    }
+}
+
+impl From<Group> for Subject {
+    fn from(g: Group) -> Self {
+        Self::new(g.name, g.gid.as_raw())
+    }
+}
+
+/// The key of a user or group mapping.
+///
+/// Keys are either names, numeric IDs such as `"#1001"`, or inclusive ID ranges such as
+/// `"#2000-2999"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key<'a> {
+    Name(&'a str),
+    Id(u32),
+    Range(u32, u32),
+}
+
+impl<'a> Key<'a> {
+    /// Parse a mapping key.
+    pub fn parse(key: &'a str) -> core::result::Result<Self, String> {
+        let id = match key.strip_prefix('#') {
+            Some(i) => i,
+            None => return Ok(Self::Name(key)),
+        };
+
+        let parse_id = |i: &str| {
+            i.trim()
+                .parse::<u32>()
+                .map_err(|_| format!("invalid ID `{}` in `{}`", i, key))
+        };
+
+        match id.split_once('-') {
+            Some((start, end)) => {
+                let (start, end) = (parse_id(start)?, parse_id(end)?);
+                if start > end {
+                    return Err(format!("empty ID range `{}`", key));
+                }
+                Ok(Self::Range(start, end))
+            }
+            None => Ok(Self::Id(parse_id(id)?)),
+        }
+    }
+
+    /// Check if this key matches a user or group.
+    #[must_use]
+    pub fn matches(&self, subject: &Subject) -> bool {
+        match *self {
+            Self::Name(n) => n == subject.name,
+            Self::Id(i) => i == subject.id,
+            Self::Range(start, end) => (start..=end).contains(&subject.id),
+        }
+    }
+
+    /// Precedence of this key among others matching the same subject. Lower values are
+    /// preferred, so names are preferred over IDs, which are preferred over ranges.
+    #[must_use]
+    pub fn specificity(&self) -> u8 {
+        match self {
+            Self::Name(_) => 0,
+            Self::Id(_) => 1,
+            Self::Range(..) => 2,
+        }
+    }
+}
+
+/// Maps a user or group to a predefined policy.
+///
+/// This can be written either as the name of a policy, or as a table with additional conditions.
+///
+/// ```toml
+/// [groups]
+/// wheel = "default"
+/// dbadmins = { policy = "root", hosts = ["db-*"] }
+/// ops = { policy = "ops", priority = 10 }
+/// ```
+#[readonly::make]
+#[derive(Debug, serde::Serialize, Clone, PartialEq, Eq)]
+pub struct Mapping {
+    /// Name of the policy to use.
+    pub policy: String,
+    /// Host name patterns this mapping is restricted to. Applies to all hosts if not specified.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hosts: Option<Vec<String>>,
+    /// Precedence of this mapping over others that apply to the same user. Higher values are
+    /// preferred.
+    #[serde(default = "defaults::priority")]
+    pub priority: i64,
+}
+
+/// Table representation of a [`Mapping`].
+#[derive(serde::Deserialize)]
+struct MappingTable {
+    policy: String,
+    #[serde(default = "defaults::hosts")]
+    hosts: Option<Vec<String>>,
+    #[serde(default = "defaults::priority")]
+    priority: i64,
+}
+
+impl<'de> serde::Deserialize<'de> for Mapping {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> core::result::Result<Self, D::Error> {
+        Ok(match serde::Deserialize::deserialize(deserializer)? {
+            utils::StringOrTable::String(policy) => Self::new(policy),
+            utils::StringOrTable::Table(MappingTable {
+                policy,
+                hosts,
+                priority,
+            }) => Self {
+                policy,
+                hosts,
+                priority,
+            },
+        })
+    }
+}
+
+impl Mapping {
+    /// Create a mapping to a policy that applies on all hosts.
+    #[must_use]
+    pub fn new<S: Into<String>>(policy: S) -> Self {
+        Self {
+            policy: policy.into(),
+            hosts: defaults::hosts(),
+            priority: defaults::priority(),
+        }
+    }
+
+    /// Check if this mapping applies on a host.
+    #[must_use]
+    pub fn applies_to_host(&self, host: &str) -> bool {
+        utils::matches_any_host(self.hosts.as_deref(), host)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mapping_hosts() {
+        let plain: Mapping = toml::Value::String("default".into()).try_into().unwrap();
+        assert_eq!(plain, Mapping::new("default"));
+        assert!(plain.applies_to_host("anything"));
+
+        let scoped: Mapping = toml::from_str("policy = 'root'\nhosts = ['db-*']").unwrap();
+        assert!(scoped.applies_to_host("db-01"));
+        assert!(scoped.applies_to_host("DB-02.example.com"));
+        assert!(!scoped.applies_to_host("web-01"));
+    }
+
+    #[test]
+    fn test_keys() {
+        let svc = Subject::new("svc-backup", 2042);
+
+        assert_eq!(Key::parse("svc-backup"), Ok(Key::Name("svc-backup")));
+        assert_eq!(Key::parse("#2042"), Ok(Key::Id(2042)));
+        assert_eq!(Key::parse("#2000-2999"), Ok(Key::Range(2000, 2999)));
+        assert!(Key::parse("#svc").is_err());
+        assert!(Key::parse("#2999-2000").is_err());
+
+        assert!(Key::Name("svc-backup").matches(&svc));
+        assert!(Key::Id(2042).matches(&svc));
+        assert!(Key::Range(2000, 2042).matches(&svc));
+        assert!(!Key::Range(2043, 2999).matches(&svc));
+        assert!(!Key::Name("2042").matches(&svc));
+    }
+}
@@ -0,0 +1,227 @@
+//! Preventing commands from running other programs.
+//!
+//! Editors and pagers can usually run a shell, which would then run with the privileges of the
+//! target. With `noexec`, a seccomp filter is installed in the child process right before it runs
+//! the command, which makes every later `execve` and `execveat` fail with `EACCES`.
+//!
+//! Since the filter must already be in place when the command is executed, the child executes the
+//! command itself, and the filter only allows that exact call: an `execve` with the pointers to
+//! the path and arguments prepared by `mk`. This stops programs from running other programs, but
+//! is not a sandbox against code written to work around it.
+
+use std::ffi::CString;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+use indexmap::IndexMap;
+use nix::libc;
+
+use crate::prelude::*;
+
+/// `AUDIT_ARCH_*` of the architecture the filter is built for.
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: u32 = 0xc000_003e;
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: u32 = 0xc000_00b7;
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+const AUDIT_ARCH: u32 = 0;
+
+/// System calls numbered from this on x86_64 use the x32 ABI.
+const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+
+// Classic BPF instruction classes and fields, from `linux/bpf_common.h`.
+const BPF_LD: u16 = 0x00;
+const BPF_JMP: u16 = 0x05;
+const BPF_RET: u16 = 0x06;
+const BPF_W: u16 = 0x00;
+const BPF_ABS: u16 = 0x20;
+const BPF_JEQ: u16 = 0x10;
+const BPF_JGE: u16 = 0x30;
+const BPF_K: u16 = 0x00;
+
+// Offsets in `struct seccomp_data`.
+const OFFSET_NR: u32 = 0;
+const OFFSET_ARCH: u32 = 4;
+const OFFSET_ARGS: u32 = 16;
+
+/// A command that is executed with a filter denying it from executing other programs.
+pub struct Program {
+    /// Path of the executable.
+    path: CString,
+    /// Arguments, starting with the path, and environment variables, which must outlive their
+    /// pointers in `argv` and `envp`.
+    _args: Vec<CString>,
+    _env: Vec<CString>,
+    /// Null terminated arrays of pointers to the arguments and environment variables.
+    argv: Vec<usize>,
+    envp: Vec<usize>,
+    /// The seccomp filter.
+    filter: Vec<libc::sock_filter>,
+}
+
+impl Program {
+    /// Prepare a command to be executed by [`Program::exec`].
+    ///
+    /// Everything is allocated here, since [`Program::exec`] runs between `fork` and `exec`.
+    ///
+    /// # Errors
+    ///
+    /// An [`io::Error`] of kind [`io::ErrorKind::Unsupported`] is returned on architectures the
+    /// filter is not available for. This also fails if any argument or variable contains a null
+    /// byte.
+    pub fn new(path: &Path, args: &[String], env: &IndexMap<String, String>) -> Result<Self> {
+        if !cfg!(any(target_arch = "x86_64", target_arch = "aarch64")) {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "noexec is not supported on this architecture",
+            )
+            .into());
+        }
+
+        let path = CString::new(path.as_os_str().as_bytes()).map_err(io::Error::from)?;
+
+        let mut all_args = vec![path.clone()];
+        for a in args {
+            all_args.push(CString::new(a.as_bytes()).map_err(io::Error::from)?);
+        }
+        let mut all_env = Vec::new();
+        for (k, v) in env {
+            all_env.push(CString::new(format!("{}={}", k, v)).map_err(io::Error::from)?);
+        }
+
+        let pointers = |strings: &[CString]| -> Vec<usize> {
+            strings
+                .iter()
+                .map(|s| s.as_ptr() as usize)
+                .chain(Some(0))
+                .collect()
+        };
+        let argv = pointers(&all_args);
+        let envp = pointers(&all_env);
+
+        // The heap buffers of `path` and `argv` don't move along with `Self`
+        let filter = filter(path.as_ptr() as u64, argv.as_ptr() as u64);
+
+        Ok(Self {
+            path,
+            _args: all_args,
+            _env: all_env,
+            argv,
+            envp,
+            filter,
+        })
+    }
+
+    /// Install the filter and execute the command, replacing this process.
+    ///
+    /// This must be called last before `exec` in the child process, since the filter denies any
+    /// other `exec`. It only returns if the command could not be executed.
+    #[allow(unsafe_code)]
+    pub fn exec(&self) -> io::Error {
+        let check = |ret: libc::c_int| match ret {
+            0 => Ok(()),
+            _ => Err(io::Error::last_os_error()),
+        };
+
+        let prog = libc::sock_fprog {
+            len: self.filter.len() as libc::c_ushort,
+            filter: self.filter.as_ptr() as *mut _,
+        };
+
+        // Arguments are read as `unsigned long`
+        let (zero, one): (libc::c_ulong, libc::c_ulong) = (0, 1);
+
+        let installed =
+            check(unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, one, zero, zero, zero) })
+                .and_then(|_| {
+                    check(unsafe {
+                        libc::prctl(
+                            libc::PR_SET_SECCOMP,
+                            libc::c_ulong::from(libc::SECCOMP_MODE_FILTER),
+                            &prog as *const libc::sock_fprog,
+                        )
+                    })
+                });
+        if let Err(e) = installed {
+            return e;
+        }
+
+        unsafe {
+            libc::execve(
+                self.path.as_ptr(),
+                self.argv.as_ptr() as *const *const libc::c_char,
+                self.envp.as_ptr() as *const *const libc::c_char,
+            )
+        };
+        io::Error::last_os_error()
+    }
+}
+
+/// A BPF statement.
+const fn stmt(code: u16, k: u32) -> libc::sock_filter {
+    libc::sock_filter {
+        code,
+        jt: 0,
+        jf: 0,
+        k,
+    }
+}
+
+/// A BPF jump, relative to the next instruction.
+const fn jump(code: u16, k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+    libc::sock_filter { code, jt, jf, k }
+}
+
+/// Build a filter that denies `execveat`, and `execve` unless called with the given path and
+/// arguments pointers.
+fn filter(path: u64, argv: u64) -> Vec<libc::sock_filter> {
+    let deny = libc::SECCOMP_RET_ERRNO | libc::EACCES as u32;
+    // Arguments are 64 bits, loaded in two halves (little endian)
+    let (arg0, arg1) = (OFFSET_ARGS, OFFSET_ARGS + 8);
+
+    vec![
+        // Deny system calls of other ABIs entirely
+        stmt(BPF_LD | BPF_W | BPF_ABS, OFFSET_ARCH),
+        jump(BPF_JMP | BPF_JEQ | BPF_K, AUDIT_ARCH, 1, 0),
+        stmt(BPF_RET | BPF_K, deny),
+        stmt(BPF_LD | BPF_W | BPF_ABS, OFFSET_NR),
+        jump(BPF_JMP | BPF_JGE | BPF_K, X32_SYSCALL_BIT, 11, 0),
+        jump(BPF_JMP | BPF_JEQ | BPF_K, libc::SYS_execveat as u32, 10, 0),
+        jump(BPF_JMP | BPF_JEQ | BPF_K, libc::SYS_execve as u32, 0, 8),
+        // `execve` by `Program::exec`
+        stmt(BPF_LD | BPF_W | BPF_ABS, arg0),
+        jump(BPF_JMP | BPF_JEQ | BPF_K, path as u32, 0, 7),
+        stmt(BPF_LD | BPF_W | BPF_ABS, arg0 + 4),
+        jump(BPF_JMP | BPF_JEQ | BPF_K, (path >> 32) as u32, 0, 5),
+        stmt(BPF_LD | BPF_W | BPF_ABS, arg1),
+        jump(BPF_JMP | BPF_JEQ | BPF_K, argv as u32, 0, 3),
+        stmt(BPF_LD | BPF_W | BPF_ABS, arg1 + 4),
+        jump(BPF_JMP | BPF_JEQ | BPF_K, (argv >> 32) as u32, 0, 1),
+        stmt(BPF_RET | BPF_K, libc::SECCOMP_RET_ALLOW),
+        stmt(BPF_RET | BPF_K, deny),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter() {
+        let filter = filter(0x7fff_0000_1000, 0x5555_0000_2000);
+
+        // Jumps stay inside the filter, which ends with a return
+        for (i, f) in filter.iter().enumerate() {
+            if f.code & 0x07 == BPF_JMP {
+                assert!(i + 1 + usize::from(f.jt.max(f.jf)) < filter.len());
+            }
+        }
+        assert_eq!(filter.last().unwrap().code, BPF_RET | BPF_K);
+
+        // Both halves of the pointers are compared
+        let constants: Vec<_> = filter.iter().map(|f| f.k).collect();
+        assert!(constants.contains(&0x7fff) && constants.contains(&0x1000));
+        assert!(constants.contains(&0x5555) && constants.contains(&0x2000));
+    }
+}
@@ -15,6 +15,8 @@ pub struct CommandOptions {
     pub args: Vec<String>,
     /// Environment variable mappings.
     pub preserve_env: Option<Vec<String>>,
+    /// Working directory requested for the command.
+    pub chdir: Option<PathBuf>,
 }
 
 #[derive(Debug, Clone)]
@@ -25,6 +27,41 @@ pub struct EditOptions {
     pub path: PathBuf,
 }
 
+/// Check configuration files.
+#[derive(Debug, Clone)]
+pub struct CheckOptions {
+    /// Configuration file to check. The system configurations are checked if not specified.
+    pub path: Option<PathBuf>,
+}
+
+/// List the permissions of a user.
+#[derive(Debug, Clone)]
+pub struct ListOptions {
+    /// User whose permissions to list. The invoking user is listed if not specified.
+    pub user: Option<User>,
+    /// Print the permissions as JSON.
+    pub json: bool,
+}
+
+/// Formats of other tools' configurations that can be imported.
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportFormat {
+    /// OpenDoas `doas.conf`.
+    Doas,
+    /// `sudoers`.
+    Sudoers,
+}
+
+/// Convert another tool's configuration file into `mk` configurations.
+#[derive(Debug, Clone)]
+pub struct ImportOptions {
+    /// Format of the file.
+    pub format: ImportFormat,
+    /// Path of the file to convert.
+    pub path: PathBuf,
+}
+
 /// All runtime options for `mk`.
 #[non_exhaustive]
 #[derive(Debug, Clone)]
@@ -32,6 +69,11 @@ pub struct EditOptions {
 pub enum MkOptions {
     None,
     Command(CommandOptions),
+    /// Explain whether a command would be permitted, without running it.
+    Explain(CommandOptions),
     Edit(EditOptions),
+    List(ListOptions),
+    Check(CheckOptions),
+    Import(ImportOptions),
     Text(String),
 }
@@ -0,0 +1,308 @@
+//! Where `mk` is invoked from.
+//!
+//! The origin of an invocation is determined from the controlling terminal and session of the
+//! process, which are kept by the kernel, and never from environment variables such as `SSH_TTY`
+//! that the invoker can set as they like.
+//!
+//! Detecting SSH sessions is best-effort: an invoker can detach from `sshd` by starting a new
+//! session, so a session only counts as local if `systemd-logind` records it as such. Anything
+//! that can't be determined, including everything on systems without `/proc`, fails closed for
+//! policies with origin rules, and is ignored by all other policies.
+
+use std::fs;
+use std::io;
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::os::unix::process::parent_id;
+use std::path::{Path, PathBuf};
+
+use nix::sys::stat::makedev;
+
+use crate::prelude::*;
+
+/// Default field values.
+mod defaults {
+    #[inline]
+    pub const fn require_tty() -> bool {
+        false
+    }
+
+    #[inline]
+    pub const fn ttys() -> Option<Vec<String>> {
+        None
+    }
+
+    #[inline]
+    pub const fn allow_ssh() -> bool {
+        true
+    }
+}
+
+/// Where `mk` was invoked from.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Origin {
+    /// Path of the controlling terminal, if there is one and it could be determined.
+    pub tty: Option<PathBuf>,
+    /// Whether the invocation is part of an SSH session, or `None` if it could not be determined.
+    pub ssh: Option<bool>,
+}
+
+impl Origin {
+    /// Get the origin of this process.
+    ///
+    /// The controlling terminal is taken from `/proc/self/stat`, and looked up by its device
+    /// number in `/dev`. The process is part of an SSH session if any of its ancestors is `sshd`,
+    /// or if its login session, as recorded by `systemd-logind`, is remote.
+    ///
+    /// This never fails. Anything that could not be determined is left unknown.
+    #[must_use]
+    pub fn current() -> Self {
+        Self {
+            tty: controlling_tty().unwrap_or(None),
+            ssh: ssh_session(),
+        }
+    }
+}
+
+/// Conditions on where a policy may be used from.
+///
+/// ```toml
+/// [policies.console.origin]
+/// ttys = ["/dev/tty[0-9]*"]
+/// allow-ssh = false
+/// ```
+#[readonly::make]
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
+pub struct Rules {
+    /// Whether a controlling terminal is required.
+    #[serde(rename = "require-tty")]
+    #[serde(default = "defaults::require_tty")]
+    pub require_tty: bool,
+    /// Patterns the path of the controlling terminal must match. A terminal is required if this
+    /// is set. Patterns are matched using [`utils::matches_pattern`].
+    #[serde(default = "defaults::ttys")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ttys: Option<Vec<String>>,
+    /// Whether the policy may be used from SSH sessions. If not, the policy may only be used from
+    /// sessions that are known to be local.
+    #[serde(rename = "allow-ssh")]
+    #[serde(default = "defaults::allow_ssh")]
+    pub allow_ssh: bool,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Self {
+            require_tty: defaults::require_tty(),
+            ttys: defaults::ttys(),
+            allow_ssh: defaults::allow_ssh(),
+        }
+    }
+}
+
+impl Rules {
+    /// Check if these rules allow an origin.
+    ///
+    /// # Errors
+    ///
+    /// Returns a description of why the origin is not allowed, such as `not available from SSH
+    /// sessions`. Origins that could not be determined are never allowed by rules that depend on
+    /// them.
+    pub fn check(&self, origin: &Origin) -> core::result::Result<(), String> {
+        match origin.ssh {
+            _ if self.allow_ssh => (),
+            Some(false) => (),
+            Some(true) => return Err("not available from SSH sessions".into()),
+            None => return Err("not available from sessions that are not known to be local".into()),
+        }
+
+        let tty = match &origin.tty {
+            Some(t) => t,
+            None if self.require_tty || self.ttys.is_some() => {
+                return Err("only available with a controlling terminal".into())
+            }
+            None => return Ok(()),
+        };
+
+        match &self.ttys {
+            Some(patterns)
+                if !patterns
+                    .iter()
+                    .any(|p| utils::matches_pattern(p, &tty.to_string_lossy())) =>
+            {
+                Err(format!("not available from terminal {}", tty.display()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Fields of `/proc/<pid>/stat` that are used here.
+#[derive(Debug, PartialEq, Eq)]
+struct Stat {
+    /// File name of the executable, possibly truncated.
+    comm: String,
+    /// Parent process ID.
+    ppid: u32,
+    /// Device number of the controlling terminal, or `0` if there is none.
+    tty_nr: u64,
+}
+
+impl Stat {
+    /// Read the status of a process.
+    fn read(pid: &str) -> Result<Self> {
+        let path = Path::new("/proc").join(pid).join("stat");
+
+        Self::parse(&fs::read_to_string(&path)?).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("could not parse {}", path.display()),
+            )
+            .into()
+        })
+    }
+
+    /// Parse the contents of `/proc/<pid>/stat`.
+    fn parse(contents: &str) -> Option<Self> {
+        // The command name may contain spaces and parentheses, so it ends at the last `)`
+        let (head, tail) = contents.rsplit_once(')')?;
+        let comm = head.split_once('(')?.1;
+
+        // state, ppid, pgrp, session, tty_nr
+        let fields: Vec<_> = tail.split_whitespace().take(5).collect();
+
+        Some(Self {
+            comm: comm.to_owned(),
+            ppid: fields.get(1)?.parse().ok()?,
+            tty_nr: fields.get(4)?.parse().ok()?,
+        })
+    }
+}
+
+/// Get the path of the controlling terminal of this process.
+fn controlling_tty() -> Result<Option<PathBuf>> {
+    let tty_nr = Stat::read("self")?.tty_nr;
+    if tty_nr == 0 {
+        return Ok(None);
+    }
+
+    // The kernel encodes the device number differently from `st_rdev`
+    let major = (tty_nr >> 8) & 0xfff;
+    let minor = (tty_nr & 0xff) | ((tty_nr >> 12) & 0xf_ff00);
+    let dev = makedev(major, minor);
+
+    let is_tty = |p: &Path| {
+        fs::symlink_metadata(p).map_or(false, |m| m.file_type().is_char_device() && m.rdev() == dev)
+    };
+
+    // Pseudo terminals
+    if (136..=143).contains(&major) {
+        let path = PathBuf::from(format!("/dev/pts/{}", (major - 136) * 256 + minor));
+        if is_tty(&path) {
+            return Ok(Some(path));
+        }
+    }
+
+    let mut entries: Vec<_> = fs::read_dir("/dev")?
+        .filter_map(|e| Some(e.ok()?.path()))
+        .collect();
+    entries.sort();
+
+    Ok(entries.into_iter().find(|p| is_tty(p)))
+}
+
+/// Check if this process is part of an SSH session.
+///
+/// Returns `None` if no ancestor is `sshd`, but the process has no login session known to
+/// `systemd-logind`, since it could have been detached from `sshd`.
+fn ssh_session() -> Option<bool> {
+    // Ancestors, until one could not be read or `init` is reached
+    let mut pid = parent_id();
+    while pid > 1 {
+        let stat = match Stat::read(&pid.to_string()) {
+            Ok(s) => s,
+            Err(_) => break,
+        };
+        // `sshd-session` since OpenSSH 9.8
+        if stat.comm == "sshd" || stat.comm == "sshd-session" {
+            return Some(true);
+        }
+        pid = stat.ppid;
+    }
+
+    // The login session, which can't be left by detaching from ancestors
+    let id = fs::read_to_string("/proc/self/sessionid").ok()?;
+    if id.trim() == "4294967295" {
+        return None;
+    }
+    let session = fs::read_to_string(Path::new("/run/systemd/sessions").join(id.trim())).ok()?;
+
+    Some(
+        session
+            .lines()
+            .any(|l| l == "REMOTE=1" || l == "SERVICE=sshd"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        let console: Rules =
+            toml::from_str("ttys = ['/dev/tty[0-9]*']\nallow-ssh = false").unwrap();
+        let tty: Rules = toml::from_str("require-tty = true").unwrap();
+
+        let local = Origin {
+            tty: Some("/dev/tty1".into()),
+            ssh: Some(false),
+        };
+        let pts = Origin {
+            tty: Some("/dev/pts/3".into()),
+            ssh: Some(false),
+        };
+        let ssh = Origin {
+            tty: Some("/dev/tty1".into()),
+            ssh: Some(true),
+        };
+        let none = Origin {
+            tty: None,
+            ssh: Some(false),
+        };
+        let unknown = Origin::default();
+
+        assert!(console.check(&local).is_ok());
+        assert!(console.check(&pts).is_err());
+        assert!(console.check(&ssh).is_err());
+        assert!(console.check(&none).is_err());
+
+        assert!(tty.check(&pts).is_ok());
+        assert!(tty.check(&ssh).is_ok());
+        assert!(tty.check(&none).is_err());
+
+        assert!(Rules::default().check(&none).is_ok());
+
+        // Unknown origins only pass rules that don't depend on them
+        assert!(Rules::default().check(&unknown).is_ok());
+        assert!(tty.check(&unknown).is_err());
+        let local_only: Rules = toml::from_str("allow-ssh = false").unwrap();
+        assert!(local_only.check(&none).is_ok());
+        assert_eq!(
+            local_only.check(&unknown),
+            Err("not available from sessions that are not known to be local".into())
+        );
+    }
+
+    #[test]
+    fn test_parse_stat() {
+        assert_eq!(
+            Stat::parse("1234 (a (b) c) S 1000 1234 1234 34816 1234 4194560 0 0"),
+            Some(Stat {
+                comm: "a (b) c".into(),
+                ppid: 1000,
+                tty_nr: 34816,
+            })
+        );
+        assert_eq!(Stat::parse("1234 (sshd) S"), None);
+    }
+}
@@ -1,6 +1,14 @@
 //! User and group permits.
 
+use std::fmt;
+use std::path::Path;
+
+use crate::mapping::{Key, Subject};
+use crate::prelude::*;
+
 mod defaults {
+    use super::*;
+
     #[inline]
     pub const fn targets() -> Vec<String> {
         Vec::new()
@@ -10,19 +18,222 @@ mod defaults {
     pub const fn all_targets() -> bool {
         false
     }
+
+    #[inline]
+    pub const fn commands() -> Option<Vec<CommandRule>> {
+        None
+    }
+
+    #[inline]
+    pub const fn deny_targets() -> Vec<String> {
+        Vec::new()
+    }
+
+    #[inline]
+    pub const fn deny_commands() -> Vec<CommandRule> {
+        Vec::new()
+    }
+}
+
+/// A command that a user or group is allowed to run.
+///
+/// This can be written either as a plain string, which permits an executable with any arguments,
+/// or as a table with additional restrictions on the arguments.
+///
+/// ```toml
+/// commands = [
+///     "/usr/local/admin/bin/*",
+///     { command = "/usr/bin/systemctl", args = ["restart", "nginx"] },
+///     { command = "/usr/bin/journalctl", args-prefix = ["-u", "nginx*"] },
+///     { command = "/usr/bin/vim", noexec = true },
+/// ]
+/// ```
+#[readonly::make]
+#[derive(Debug, serde::Serialize, Clone, PartialEq, Eq)]
+pub struct CommandRule {
+    /// Pattern matching the absolute path of the executable.
+    pub command: String,
+    /// Patterns that all arguments must match exactly, one pattern per argument.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub args: Option<Vec<String>>,
+    /// Patterns that the leading arguments must match. Any further arguments are permitted.
+    #[serde(rename = "args-prefix")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub args_prefix: Option<Vec<String>>,
+    /// Prevent the command from executing other programs. See [`noexec`](crate::noexec).
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub noexec: bool,
+}
+
+/// Table representation of a [`CommandRule`].
+#[derive(serde::Deserialize)]
+struct CommandRuleTable {
+    command: String,
+    #[serde(default)]
+    args: Option<Vec<String>>,
+    #[serde(rename = "args-prefix")]
+    #[serde(default)]
+    args_prefix: Option<Vec<String>>,
+    #[serde(default)]
+    noexec: bool,
+}
+
+impl<'de> serde::Deserialize<'de> for CommandRule {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> core::result::Result<Self, D::Error> {
+        Ok(match serde::Deserialize::deserialize(deserializer)? {
+            utils::StringOrTable::String(command) => Self::new(command),
+            utils::StringOrTable::Table(CommandRuleTable {
+                command,
+                args,
+                args_prefix,
+                noexec,
+            }) => Self {
+                command,
+                args,
+                args_prefix,
+                noexec,
+            },
+        })
+    }
+}
+
+impl CommandRule {
+    /// Create a rule permitting an executable with any arguments.
+    #[must_use]
+    pub fn new<S: Into<String>>(command: S) -> Self {
+        Self {
+            command: command.into(),
+            args: None,
+            args_prefix: None,
+            noexec: false,
+        }
+    }
+
+    /// Check if this rule permits running an executable with the given arguments.
+    ///
+    /// Patterns are matched using [`utils::matches_pattern`].
+    #[must_use]
+    pub fn matches(&self, path: &Path, args: &[String]) -> bool {
+        let path = match path.to_str() {
+            Some(p) => p,
+            None => return false,
+        };
+
+        if !utils::matches_pattern(&self.command, path) {
+            return false;
+        }
+
+        let all_match = |patterns: &[String], args: &[String]| {
+            patterns
+                .iter()
+                .zip(args)
+                .all(|(p, a)| utils::matches_pattern(p, a))
+        };
+
+        if let Some(a) = &self.args {
+            if a.len() != args.len() || !all_match(a, args) {
+                return false;
+            }
+        }
+
+        if let Some(a) = &self.args_prefix {
+            if a.len() > args.len() || !all_match(a, args) {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+impl fmt::Display for CommandRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.command)?;
+
+        match (&self.args, &self.args_prefix) {
+            (Some(args), _) => args.iter().try_for_each(|a| write!(f, " {}", a)),
+            (None, Some(prefix)) => {
+                prefix.iter().try_for_each(|a| write!(f, " {}", a))?;
+                write!(f, " ...")
+            }
+            (None, None) => Ok(()),
+        }?;
+
+        if self.noexec {
+            write!(f, " (noexec)")?;
+        }
+        Ok(())
+    }
+}
+
+/// A target specification.
+///
+/// Targets are either user names, members of a group such as `"%wheel"`, UIDs such as `"#1001"`
+/// or inclusive UID ranges such as `"#100-999"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target<'a> {
+    User(Key<'a>),
+    Group(&'a str),
+}
+
+impl<'a> Target<'a> {
+    /// Parse a target specification.
+    pub fn parse(spec: &'a str) -> core::result::Result<Self, String> {
+        match spec.strip_prefix('%') {
+            Some(g) => Ok(Self::Group(g)),
+            None => Key::parse(spec).map(Self::User),
+        }
+    }
+
+    /// Check if this specification matches a target user who is a member of `groups`.
+    #[must_use]
+    pub fn matches(&self, target: &Subject, groups: &[Subject]) -> bool {
+        match self {
+            Self::User(k) => k.matches(target),
+            Self::Group(g) => groups.iter().any(|s| s.name == *g),
+        }
+    }
+}
+
+/// Check if a target specification matches. Invalid specifications never match.
+#[must_use]
+pub fn matches_target(spec: &str, target: &Subject, groups: &[Subject]) -> bool {
+    Target::parse(spec).map_or(false, |t| t.matches(target, groups))
 }
 
 /// Definitions for all actions a user or group is allowed to do.
+///
+/// Deny rules always take precedence over any permitted targets or commands.
 #[readonly::make]
 #[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
 pub struct Permits {
-    /// Permitted targets.
+    /// Permitted targets. See [`Target`].
     #[serde(default = "defaults::targets")]
     pub targets: Vec<String>,
     /// Permit running as all targets. This will cause the session to ignore the `permitted` field.
     #[serde(rename = "all-targets")]
     #[serde(default = "defaults::all_targets")]
     pub all_targets: bool,
+    /// Permitted commands. All commands are permitted if this is not specified.
+    #[serde(default = "defaults::commands")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub commands: Option<Vec<CommandRule>>,
+    /// Targets that are never permitted. See [`Target`].
+    #[serde(rename = "deny-targets")]
+    #[serde(default = "defaults::deny_targets")]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub deny_targets: Vec<String>,
+    /// Commands that are never permitted.
+    #[serde(rename = "deny-commands")]
+    #[serde(default = "defaults::deny_commands")]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub deny_commands: Vec<CommandRule>,
+    /// Permits of other policies these were combined with. See [`Permits::union`].
+    #[serde(skip_deserializing)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub merged: Vec<Permits>,
 }
 
 impl Default for Permits {
@@ -30,6 +241,10 @@ impl Default for Permits {
         Self {
             targets: defaults::targets(),
             all_targets: defaults::all_targets(),
+            commands: defaults::commands(),
+            deny_targets: defaults::deny_targets(),
+            deny_commands: defaults::deny_commands(),
+            merged: Vec::new(),
         }
     }
 }
@@ -43,4 +258,76 @@ impl Permits {
             ..Self::default()
         }
     }
+
+    /// Combine these permits with another, permitting everything that either permits.
+    ///
+    /// The targets and commands of each are kept apart in [`Permits::merged`], so a request is
+    /// only permitted if one of them permits both its target and its command. The deny rules of
+    /// all of them apply, so anything either denies is still denied.
+    #[must_use]
+    pub fn union(&self, other: &Self) -> Self {
+        let mut permits = self.clone();
+        permits.merged.extend(other.sources().map(|p| Self {
+            merged: Vec::new(),
+            ..p.clone()
+        }));
+        permits
+    }
+
+    /// Iterate over these permits and all permits they were combined with.
+    pub fn sources(&self) -> impl Iterator<Item = &Self> {
+        std::iter::once(self).chain(&self.merged)
+    }
+
+    /// Check that all target specifications are valid.
+    pub fn validate(&self) -> core::result::Result<(), String> {
+        self.targets
+            .iter()
+            .chain(&self.deny_targets)
+            .try_for_each(|t| Target::parse(t).map(|_| ()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(a: &[&str]) -> Vec<String> {
+        a.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_command_rule_matches() {
+        let any = CommandRule::new("/usr/local/admin/bin/*");
+        assert!(any.matches(Path::new("/usr/local/admin/bin/rotate"), &args(&["-f"])));
+        assert!(!any.matches(Path::new("/usr/local/admin/bin/x/rotate"), &[]));
+        assert!(!any.matches(Path::new("/usr/bin/rotate"), &[]));
+
+        let exact: CommandRule =
+            toml::from_str("command = '/usr/bin/systemctl'\nargs = ['restart', 'nginx']").unwrap();
+        let systemctl = Path::new("/usr/bin/systemctl");
+        assert!(exact.matches(systemctl, &args(&["restart", "nginx"])));
+        assert!(!exact.matches(systemctl, &args(&["restart", "nginx", "sshd"])));
+        assert!(!exact.matches(systemctl, &args(&["stop", "nginx"])));
+        assert!(!exact.matches(systemctl, &[]));
+
+        let prefix: CommandRule =
+            toml::from_str("command = '/usr/bin/journalctl'\nargs-prefix = ['-u', 'nginx*']")
+                .unwrap();
+        let journalctl = Path::new("/usr/bin/journalctl");
+        assert!(prefix.matches(journalctl, &args(&["-u", "nginx.service", "-f"])));
+        assert!(!prefix.matches(journalctl, &args(&["-u"])));
+        assert!(!prefix.matches(journalctl, &args(&["-u", "sshd"])));
+    }
+
+    #[test]
+    fn test_target_specifications() {
+        let permits: Permits =
+            toml::from_str("targets = ['deploy', '%services', '#100-999']\ndeny-targets = ['#0']")
+                .unwrap();
+        assert!(permits.validate().is_ok());
+
+        let invalid: Permits = toml::from_str("targets = ['#1-x']").unwrap();
+        assert!(invalid.validate().is_err());
+    }
 }
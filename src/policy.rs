@@ -1,13 +1,84 @@
 //! A user policy.
 
+use toml::value::Datetime;
+
 use crate::auth;
+use crate::env;
+use crate::origin;
 use crate::permits;
+use crate::prelude::*;
+use crate::process;
+use crate::schedule;
 use crate::session;
 
+mod defaults {
+    use super::*;
+
+    #[inline]
+    pub const fn hosts() -> Option<Vec<String>> {
+        None
+    }
+
+    #[inline]
+    pub const fn extends() -> Option<String> {
+        None
+    }
+
+    #[inline]
+    pub const fn datetime() -> Option<Datetime> {
+        None
+    }
+
+    #[inline]
+    pub const fn windows() -> Vec<schedule::Window> {
+        Vec::new()
+    }
+}
+
+/// Deserialization of optional date times.
+///
+/// Policies are read from [`toml::Value`]s once their parents are merged in, which hand date times
+/// over as strings rather than as [`Datetime`]s.
+mod datetime_value {
+    use std::fmt;
+
+    use serde::de;
+    use toml::value::Datetime;
+
+    pub fn deserialize<'de, D: de::Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Datetime>, D::Error> {
+        struct Visitor;
+
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = Datetime;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "a TOML datetime")
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                v.parse().map_err(E::custom)
+            }
+
+            fn visit_map<A: de::MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                serde::Deserialize::deserialize(de::value::MapAccessDeserializer::new(map))
+            }
+        }
+
+        deserializer.deserialize_any(Visitor).map(Some)
+    }
+}
+
 /// A policy is a common definition for all actions and configurations for a user or group.
 #[readonly::make]
 #[derive(Debug, Default, serde::Deserialize, serde::Serialize, Clone)]
 pub struct Policy {
+    /// Name of the policy this policy extends. This is resolved when the configuration is read,
+    /// with all tables of this policy merged over those of its parent.
+    #[serde(default = "defaults::extends")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extends: Option<String>,
     /// Actions this policy allows for
     #[serde(default = "permits::Permits::default")]
     pub permits: permits::Permits,
@@ -17,6 +88,35 @@ pub struct Policy {
     /// Authenticator configuration.
     #[serde(default = "auth::Rules::default")]
     pub auth: auth::Rules,
+    /// Environment of commands.
+    #[serde(default = "env::Rules::default")]
+    pub env: env::Rules,
+    /// Attributes of command processes.
+    #[serde(default = "process::Rules::default")]
+    pub process: process::Rules,
+    /// Host name patterns this policy is restricted to. Applies to all hosts if not specified.
+    #[serde(default = "defaults::hosts")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hosts: Option<Vec<String>>,
+    /// Time from which this policy is valid.
+    #[serde(rename = "valid-from")]
+    #[serde(default = "defaults::datetime")]
+    #[serde(deserialize_with = "datetime_value::deserialize")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub valid_from: Option<Datetime>,
+    /// Time until which this policy is valid.
+    #[serde(rename = "valid-until")]
+    #[serde(default = "defaults::datetime")]
+    #[serde(deserialize_with = "datetime_value::deserialize")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub valid_until: Option<Datetime>,
+    /// Recurring windows of time in which this policy is valid. Always valid if empty.
+    #[serde(default = "defaults::windows")]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub windows: Vec<schedule::Window>,
+    /// Where this policy may be used from.
+    #[serde(default = "origin::Rules::default")]
+    pub origin: origin::Rules,
 }
 
 impl Policy {
@@ -26,7 +126,65 @@ impl Policy {
         Self {
             permits: permits::Permits::root(),
             session: session::Rules::root(),
+            env: env::Rules::root(),
+            ..Self::default()
+        }
+    }
+
+    /// Combine this policy with another that applies to the same user.
+    ///
+    /// The result permits everything that either policy permits, with the strictest session and
+    /// authenticator rules of both. Targets and commands are only permitted together if one policy
+    /// permits both (see [`permits::Permits::union`]). Environment and process rules are left
+    /// empty, since they only apply to the commands of the policy that set them (see
+    /// [`Policy::with_rules_of`]). Conditions such as hosts, schedules and origins are not carried
+    /// over, since both policies are expected to already apply.
+    #[must_use]
+    pub fn merge(&self, other: &Self) -> Self {
+        Self {
+            permits: self.permits.union(&other.permits),
+            session: self.session.strictest(&other.session),
+            auth: self.auth.strictest(&other.auth),
             ..Self::default()
         }
     }
+
+    /// Narrow a merged policy to a request permitted by `source`, one of the policies it was
+    /// merged from, by taking the environment and process rules of `source`.
+    #[must_use]
+    pub fn with_rules_of(&self, source: &Self) -> Self {
+        Self {
+            env: source.env.clone(),
+            process: source.process.clone(),
+            ..self.clone()
+        }
+    }
+
+    /// Check if this policy applies on a host.
+    #[must_use]
+    pub fn applies_to_host(&self, host: &str) -> bool {
+        utils::matches_any_host(self.hosts.as_deref(), host)
+    }
+
+    /// Check if this policy is valid at the time of a clock.
+    ///
+    /// See [`schedule::check`].
+    pub fn check_schedule(
+        &self,
+        clock: &schedule::Clock,
+    ) -> core::result::Result<(), schedule::Inactive> {
+        schedule::check(
+            self.valid_from.as_ref(),
+            self.valid_until.as_ref(),
+            &self.windows,
+            clock,
+        )
+    }
+
+    /// Check if this policy may be used from an origin.
+    ///
+    /// See [`origin::Rules::check`].
+    pub fn check_origin(&self, origin: &origin::Origin) -> core::result::Result<(), String> {
+        self.origin.check(origin)
+    }
 }
@@ -0,0 +1,551 @@
+//! Attributes of command processes.
+//!
+//! Commands would otherwise inherit the umask, resource limits and scheduling priority of the
+//! invoker. These are applied in the child process after it is forked, before privileges are
+//! dropped, so that limits can also be raised.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use nix::libc;
+use nix::sys::stat::{umask, Mode};
+
+use crate::capabilities;
+use crate::prelude::*;
+
+/// Default field values.
+mod defaults {
+    use super::*;
+
+    #[inline]
+    pub const fn umask() -> Option<u32> {
+        None
+    }
+
+    #[inline]
+    pub const fn limit() -> Option<Limit> {
+        None
+    }
+
+    #[inline]
+    pub const fn nice() -> Option<i32> {
+        None
+    }
+
+    #[inline]
+    pub const fn ionice() -> Option<IoClass> {
+        None
+    }
+
+    #[inline]
+    pub const fn ionice_level() -> Option<u8> {
+        None
+    }
+
+    #[inline]
+    pub const fn dir() -> Option<PathBuf> {
+        None
+    }
+
+    #[inline]
+    pub const fn allow_chdir() -> Vec<String> {
+        Vec::new()
+    }
+
+    #[inline]
+    pub const fn noexec() -> bool {
+        false
+    }
+
+    #[inline]
+    pub const fn capabilities() -> Vec<String> {
+        Vec::new()
+    }
+}
+
+/// A resource limit.
+///
+/// Written either as a single value used as both the soft and hard limit, or as a table with
+/// `soft` and `hard` values. A value of `-1` means unlimited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub struct Limit {
+    /// Soft limit, or [`None`] if unlimited.
+    #[serde(with = "limit_value")]
+    pub soft: Option<u64>,
+    /// Hard limit, or [`None`] if unlimited.
+    #[serde(with = "limit_value")]
+    pub hard: Option<u64>,
+}
+
+impl std::fmt::Display for Limit {
+    /// Formats the limit as `soft/hard`, or as a single value if both are the same.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let value = |v: Option<u64>| v.map_or_else(|| "unlimited".to_owned(), |v| v.to_string());
+
+        if self.soft == self.hard {
+            write!(f, "{}", value(self.soft))
+        } else {
+            write!(f, "{}/{}", value(self.soft), value(self.hard))
+        }
+    }
+}
+
+/// Table representation of a [`Limit`].
+#[derive(serde::Deserialize)]
+struct LimitTable {
+    #[serde(with = "limit_value")]
+    soft: Option<u64>,
+    #[serde(with = "limit_value")]
+    hard: Option<u64>,
+}
+
+impl<'de> serde::Deserialize<'de> for Limit {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> core::result::Result<Self, D::Error> {
+        struct Visitor;
+
+        impl<'de> serde::de::Visitor<'de> for Visitor {
+            type Value = Limit;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                write!(f, "an integer or a table")
+            }
+
+            fn visit_i64<E: serde::de::Error>(
+                self,
+                v: i64,
+            ) -> core::result::Result<Self::Value, E> {
+                let value = limit_value::from_i64(v)?;
+                Ok(Limit {
+                    soft: value,
+                    hard: value,
+                })
+            }
+
+            fn visit_map<A: serde::de::MapAccess<'de>>(
+                self,
+                map: A,
+            ) -> core::result::Result<Self::Value, A::Error> {
+                let LimitTable { soft, hard } = serde::Deserialize::deserialize(
+                    serde::de::value::MapAccessDeserializer::new(map),
+                )?;
+                Ok(Limit { soft, hard })
+            }
+        }
+
+        deserializer.deserialize_any(Visitor)
+    }
+}
+
+/// (De)serialization of limit values, where `-1` means unlimited.
+mod limit_value {
+    use serde::{Deserialize, Serialize};
+
+    pub fn from_i64<E: serde::de::Error>(v: i64) -> Result<Option<u64>, E> {
+        match v {
+            -1 => Ok(None),
+            v if v >= 0 => Ok(Some(v as u64)),
+            v => Err(E::invalid_value(
+                serde::de::Unexpected::Signed(v),
+                &"a non-negative integer or -1",
+            )),
+        }
+    }
+
+    pub fn serialize<S: serde::Serializer>(
+        value: &Option<u64>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        value.map_or(-1, |v| v as i64).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<u64>, D::Error> {
+        from_i64(i64::deserialize(deserializer)?)
+    }
+}
+
+/// Resource limits of commands. Limits that are not set are inherited from the invoker.
+#[readonly::make]
+#[derive(Debug, Default, serde::Deserialize, serde::Serialize, Clone, PartialEq, Eq)]
+pub struct Limits {
+    /// Maximum number of open files.
+    #[serde(default = "defaults::limit")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nofile: Option<Limit>,
+    /// Maximum number of processes of the target user.
+    #[serde(default = "defaults::limit")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nproc: Option<Limit>,
+    /// Maximum size of core dumps, in bytes.
+    #[serde(default = "defaults::limit")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub core: Option<Limit>,
+    /// Maximum size of the address space, in bytes.
+    #[serde(rename = "as")]
+    #[serde(default = "defaults::limit")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address_space: Option<Limit>,
+    /// Maximum CPU time, in seconds.
+    #[serde(default = "defaults::limit")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpu: Option<Limit>,
+}
+
+/// I/O scheduling class, as set by `ionice`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum IoClass {
+    Realtime,
+    BestEffort,
+    Idle,
+}
+
+impl std::fmt::Display for IoClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Realtime => "realtime",
+            Self::BestEffort => "best-effort",
+            Self::Idle => "idle",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Attributes of command processes.
+///
+/// ```toml
+/// [policies.default.process]
+/// umask = 0o022
+/// nice = 10
+/// ionice = "best-effort"
+/// ionice-level = 7
+/// limits = { nofile = 4096, core = 0, cpu = { soft = 60, hard = 120 } }
+/// chroot = "/srv/chroots/build"
+/// chdir = "/work"
+/// allow-chdir = ["/work/*"]
+/// ```
+#[readonly::make]
+#[derive(Debug, Default, serde::Deserialize, serde::Serialize, Clone, PartialEq, Eq)]
+pub struct Rules {
+    /// File mode creation mask.
+    #[serde(default = "defaults::umask")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub umask: Option<u32>,
+    /// Resource limits.
+    #[serde(default = "Limits::default")]
+    pub limits: Limits,
+    /// Scheduling priority, from `-20` (highest) to `19` (lowest).
+    #[serde(default = "defaults::nice")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nice: Option<i32>,
+    /// I/O scheduling class.
+    #[serde(default = "defaults::ionice")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ionice: Option<IoClass>,
+    /// I/O scheduling priority within the class, from `0` (highest) to `7` (lowest). Only the
+    /// realtime and best-effort classes have levels. Defaults to `4`.
+    #[serde(rename = "ionice-level")]
+    #[serde(default = "defaults::ionice_level")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ionice_level: Option<u8>,
+    /// Working directory of commands, inside the chroot if there is one. Commands run in the
+    /// invoker's working directory if this is not set, or in `/` with a chroot.
+    #[serde(default = "defaults::dir")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chdir: Option<PathBuf>,
+    /// Patterns of directories the invoker may run commands in with `--chdir`. Patterns are
+    /// matched using [`utils::matches_pattern`].
+    #[serde(rename = "allow-chdir")]
+    #[serde(default = "defaults::allow_chdir")]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub allow_chdir: Vec<String>,
+    /// Directory to change the root directory of commands to. Like configuration files, it must
+    /// only be modifiable by root (see [`utils::check_secure_path`]).
+    #[serde(default = "defaults::dir")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chroot: Option<PathBuf>,
+    /// Prevent commands from executing other programs. See [`noexec`](crate::noexec).
+    #[serde(default = "defaults::noexec")]
+    pub noexec: bool,
+    /// Linux capabilities granted to commands, such as `CAP_NET_BIND_SERVICE`. All other
+    /// capabilities are dropped, even for root targets. See [`capabilities`].
+    #[serde(default = "defaults::capabilities")]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub capabilities: Vec<String>,
+}
+
+impl Rules {
+    /// Check that all attributes are within their ranges, that directories are absolute and that
+    /// capabilities are known.
+    pub fn validate(&self) -> core::result::Result<(), String> {
+        capabilities::Set::from_names(&self.capabilities)?;
+
+        for (name, dir) in [("chdir", &self.chdir), ("chroot", &self.chroot)] {
+            if let Some(d) = dir {
+                if d.is_relative() {
+                    return Err(format!("{} must be absolute, found {}", name, d.display()));
+                }
+            }
+        }
+
+        if let Some(m) = self.umask {
+            if m > 0o777 {
+                return Err(format!("invalid umask {:o}", m));
+            }
+        }
+
+        if let Some(n) = self.nice {
+            if !(-20..=19).contains(&n) {
+                return Err(format!("nice must be between -20 and 19, found {}", n));
+            }
+        }
+
+        match (self.ionice, self.ionice_level) {
+            (_, Some(l)) if l > 7 => {
+                return Err(format!("ionice-level must be between 0 and 7, found {}", l))
+            }
+            (None, Some(_)) | (Some(IoClass::Idle), Some(_)) => {
+                return Err("ionice-level requires the realtime or best-effort class".into())
+            }
+            _ => {}
+        }
+
+        for (name, limit) in self.limits.iter() {
+            let exceeds = match (limit.soft, limit.hard) {
+                (Some(s), Some(h)) => s > h,
+                (None, Some(_)) => true,
+                (_, None) => false,
+            };
+            if exceeds {
+                return Err(format!("soft limit of {} exceeds its hard limit", name));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Get the working directory of a command, given the directory requested by the invoker.
+    ///
+    /// Returns [`None`] if the invoker's working directory should be kept.
+    ///
+    /// # Errors
+    ///
+    /// An [`io::Error`] of kind [`io::ErrorKind::PermissionDenied`] is returned if the requested
+    /// directory does not match any pattern in `allow-chdir`. With a chroot, the requested
+    /// directory must be absolute.
+    pub fn working_dir(&self, requested: Option<&Path>) -> Result<Option<PathBuf>> {
+        let requested = match requested {
+            Some(d) if d.is_relative() && self.chroot.is_some() => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "working directory must be absolute inside a chroot: {}",
+                        d.display()
+                    ),
+                )
+                .into())
+            }
+            Some(d) => utils::normalize_path(d),
+            None => {
+                return Ok(self
+                    .chdir
+                    .clone()
+                    .or_else(|| self.chroot.as_ref().map(|_| PathBuf::from("/"))))
+            }
+        };
+
+        let permitted = requested.to_str().map_or(false, |d| {
+            self.allow_chdir
+                .iter()
+                .any(|p| utils::matches_pattern(p, d))
+        });
+        if !permitted {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("not permitted to run in {}", requested.display()),
+            )
+            .into());
+        }
+
+        Ok(Some(requested))
+    }
+
+    /// Apply these rules to this process.
+    ///
+    /// This is called in the child process between `fork` and `exec`, so it only makes system
+    /// calls, and never allocates.
+    pub fn apply(&self) -> io::Result<()> {
+        if let Some(m) = self.umask {
+            umask(Mode::from_bits_truncate(m));
+        }
+
+        for (resource, limit) in self.limits.resources() {
+            set_limit(resource, limit)?;
+        }
+
+        if let Some(n) = self.nice {
+            set_priority(n)?;
+        }
+
+        if let Some(class) = self.ionice {
+            let (class, level) = match class {
+                IoClass::Realtime => (1, self.ionice_level.unwrap_or(4)),
+                IoClass::BestEffort => (2, self.ionice_level.unwrap_or(4)),
+                IoClass::Idle => (3, 0),
+            };
+            set_io_priority(class, level)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Limits {
+    /// Iterate over the limits that are set, along with their names.
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, &Limit)> {
+        [
+            ("nofile", &self.nofile),
+            ("nproc", &self.nproc),
+            ("core", &self.core),
+            ("as", &self.address_space),
+            ("cpu", &self.cpu),
+        ]
+        .into_iter()
+        .filter_map(|(name, limit)| Some((name, limit.as_ref()?)))
+    }
+
+    /// Iterate over the limits that are set, along with their resources.
+    fn resources(&self) -> impl Iterator<Item = (Resource, &Limit)> {
+        [
+            (libc::RLIMIT_NOFILE, &self.nofile),
+            (libc::RLIMIT_NPROC, &self.nproc),
+            (libc::RLIMIT_CORE, &self.core),
+            (libc::RLIMIT_AS, &self.address_space),
+            (libc::RLIMIT_CPU, &self.cpu),
+        ]
+        .into_iter()
+        .filter_map(|(resource, limit)| Some((resource, limit.as_ref()?)))
+    }
+}
+
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+type Resource = libc::__rlimit_resource_t;
+#[cfg(not(all(target_os = "linux", target_env = "gnu")))]
+type Resource = libc::c_int;
+
+/// Set a resource limit of this process.
+#[allow(unsafe_code)]
+fn set_limit(resource: Resource, limit: &Limit) -> io::Result<()> {
+    let limit = libc::rlimit {
+        rlim_cur: limit.soft.unwrap_or(libc::RLIM_INFINITY),
+        rlim_max: limit.hard.unwrap_or(libc::RLIM_INFINITY),
+    };
+
+    match unsafe { libc::setrlimit(resource, &limit) } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+/// Set the scheduling priority of this process.
+#[allow(unsafe_code)]
+fn set_priority(nice: i32) -> io::Result<()> {
+    match unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, nice) } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+/// Set the I/O scheduling class and level of this process.
+#[allow(unsafe_code)]
+fn set_io_priority(class: libc::c_int, level: u8) -> io::Result<()> {
+    /// `IOPRIO_WHO_PROCESS` from `linux/ioprio.h`.
+    const WHO_PROCESS: libc::c_int = 1;
+    /// `IOPRIO_CLASS_SHIFT` from `linux/ioprio.h`.
+    const CLASS_SHIFT: libc::c_int = 13;
+
+    let priority = class << CLASS_SHIFT | libc::c_int::from(level);
+    match unsafe { libc::syscall(libc::SYS_ioprio_set, WHO_PROCESS, 0, priority) } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rules() {
+        let rules: Rules = toml::from_str(
+            "umask = 0o027\nnice = 5\nionice = 'best-effort'\n\
+             [limits]\nnofile = 4096\ncore = 0\ncpu = { soft = 60, hard = -1 }",
+        )
+        .unwrap();
+        rules.validate().unwrap();
+
+        assert_eq!(rules.umask, Some(0o027));
+        assert_eq!(
+            rules.limits.nofile,
+            Some(Limit {
+                soft: Some(4096),
+                hard: Some(4096)
+            })
+        );
+        assert_eq!(
+            rules.limits.cpu,
+            Some(Limit {
+                soft: Some(60),
+                hard: None
+            })
+        );
+        assert_eq!(rules.limits.resources().count(), 3);
+
+        let limits: Vec<_> = rules
+            .limits
+            .iter()
+            .map(|(name, limit)| format!("{}={}", name, limit))
+            .collect();
+        assert_eq!(limits, ["nofile=4096", "core=0", "cpu=60/unlimited"]);
+
+        for invalid in [
+            "umask = 0o1777",
+            "nice = 20",
+            "ionice = 'idle'\nionice-level = 1",
+            "limits = { nproc = { soft = 10, hard = 5 } }",
+            "limits = { nproc = { soft = -1, hard = 5 } }",
+            "capabilities = ['CAP_NET_BIND_SERVICE', 'CAP_EVERYTHING']",
+        ] {
+            let rules: Rules = toml::from_str(invalid).unwrap();
+            assert!(rules.validate().is_err(), "{}", invalid);
+        }
+        assert!(toml::from_str::<Rules>("limits = { core = -2 }").is_err());
+    }
+
+    #[test]
+    fn test_working_dir() {
+        let rules: Rules = toml::from_str("allow-chdir = ['/srv/*']").unwrap();
+        assert_eq!(rules.working_dir(None).unwrap(), None);
+        assert_eq!(
+            rules.working_dir(Some(Path::new("/srv/a"))).unwrap(),
+            Some("/srv/a".into())
+        );
+        assert!(rules.working_dir(Some(Path::new("/srv/a/b"))).is_err());
+        assert!(rules
+            .working_dir(Some(Path::new("/srv/a/../../etc")))
+            .is_err());
+
+        let rules: Rules =
+            toml::from_str("chroot = '/srv/root'\nallow-chdir = ['/work/**']").unwrap();
+        assert_eq!(rules.working_dir(None).unwrap(), Some("/".into()));
+        assert!(rules.working_dir(Some(Path::new("/work/x/y"))).is_ok());
+        assert!(rules.working_dir(Some(Path::new("work"))).is_err());
+        assert!(toml::from_str::<Rules>("chdir = 'work'")
+            .unwrap()
+            .validate()
+            .is_err());
+    }
+}
@@ -0,0 +1,683 @@
+//! Time based policy restrictions.
+//!
+//! All local times, such as those in [`Window`]s or dates without an offset, are interpreted in
+//! the system time zone, as defined by `/etc/localtime`. The `TZ` environment variable is never
+//! consulted, since it is controlled by the invoking user. If `/etc/localtime` does not exist,
+//! local times are interpreted as UTC. If it can't be read, schedules that depend on local times
+//! are never active, while all other schedules still apply.
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::time::SystemTime;
+
+use toml::value::Datetime;
+use tz::datetime::{DateTime, FoundDateTimeKind, UtcDateTime};
+use tz::{TimeZone, TimeZoneRef};
+
+/// Path to the system time zone definition.
+const LOCALTIME_PATH: &str = "/etc/localtime";
+
+const SECONDS_PER_DAY: i64 = 86_400;
+
+mod defaults {
+    use super::*;
+
+    #[inline]
+    pub const fn days() -> Option<Vec<Weekday>> {
+        None
+    }
+
+    #[inline]
+    pub const fn hours() -> Option<HourRange> {
+        None
+    }
+}
+
+/// A point in time, and the time zone in which it is to be interpreted.
+#[derive(Debug, Clone)]
+pub struct Clock {
+    /// The time zone, or why it could not be read.
+    time_zone: core::result::Result<TimeZone, String>,
+    now: i64,
+}
+
+impl Clock {
+    /// Create a clock at some unix time in the given time zone.
+    #[must_use]
+    pub fn new(time_zone: TimeZone, now: i64) -> Self {
+        Self {
+            time_zone: Ok(time_zone),
+            now,
+        }
+    }
+
+    /// The current time in the system time zone.
+    ///
+    /// If the system time zone can't be read, local times can't be interpreted with this clock.
+    /// See [`Inactive::UnknownTimeZone`].
+    #[must_use]
+    pub fn system() -> Self {
+        let time_zone = match fs::read(LOCALTIME_PATH) {
+            Ok(b) => TimeZone::from_tz_data(&b[..]).map_err(|e| e.to_string()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(TimeZone::utc()),
+            Err(e) => Err(e.to_string()),
+        };
+
+        let now = match SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+            Ok(d) => d.as_secs() as i64,
+            Err(e) => -(e.duration().as_secs() as i64),
+        };
+
+        Self {
+            time_zone: time_zone.map_err(|e| format!("{}: {}", LOCALTIME_PATH, e)),
+            now,
+        }
+    }
+
+    /// Unix time of this clock.
+    #[must_use]
+    #[inline]
+    pub fn now(&self) -> i64 {
+        self.now
+    }
+
+    /// Time zone of this clock, which is needed to interpret local times.
+    fn time_zone(&self) -> core::result::Result<TimeZoneRef<'_>, Inactive> {
+        match &self.time_zone {
+            Ok(tz) => Ok(tz.as_ref()),
+            Err(e) => Err(Inactive::UnknownTimeZone(e.clone())),
+        }
+    }
+
+    /// Local date and time of this clock.
+    fn local(&self) -> core::result::Result<DateTime, Inactive> {
+        DateTime::from_timespec(self.now, 0, self.time_zone()?)
+            .map_err(|_| Inactive::OutsideWindows)
+    }
+
+    /// Unix time of a local date and time in a time zone.
+    ///
+    /// Ambiguous local times resolve to the earliest match. Local times that are skipped by a
+    /// forward transition resolve to the instant of the transition.
+    fn unix_time_of(
+        time_zone: TimeZoneRef<'_>,
+        year: i32,
+        month: u8,
+        day: u8,
+        hms: (u8, u8, u8),
+    ) -> Option<i64> {
+        let found = DateTime::find(year, month, day, hms.0, hms.1, hms.2, 0, time_zone).ok()?;
+
+        if let Some(d) = found.earliest() {
+            return Some(d.unix_time());
+        }
+
+        found.into_inner().into_iter().next().map(|k| match k {
+            FoundDateTimeKind::Skipped {
+                after_transition, ..
+            } => after_transition.unix_time(),
+            FoundDateTimeKind::Normal(d) => d.unix_time(),
+        })
+    }
+
+    /// Unix time at which a configured date time begins.
+    ///
+    /// Dates without a time begin at local midnight. If `end_of_day` is set, a date without a time
+    /// instead refers to the following local midnight. Only date times with an offset can be
+    /// resolved without a time zone.
+    fn resolve(
+        &self,
+        datetime: &Datetime,
+        end_of_day: bool,
+    ) -> core::result::Result<i64, Inactive> {
+        let invalid = || Inactive::Invalid(datetime.clone());
+        let parsed = ParsedDatetime::parse(&datetime.to_string()).ok_or_else(invalid)?;
+
+        if let Some(offset) = parsed.offset {
+            let (h, m, s) = parsed.time.ok_or_else(invalid)?;
+            let utc = UtcDateTime::new(parsed.year, parsed.month, parsed.day, h, m, s, 0)
+                .map_err(|_| invalid())?;
+            return Ok(utc.unix_time() - i64::from(offset));
+        }
+
+        let time_zone = self.time_zone()?;
+        let local = |year, month, day, hms| {
+            Self::unix_time_of(time_zone, year, month, day, hms).ok_or_else(invalid)
+        };
+
+        match parsed.time {
+            Some(t) => local(parsed.year, parsed.month, parsed.day, t),
+            None if end_of_day => {
+                let utc = UtcDateTime::new(parsed.year, parsed.month, parsed.day, 0, 0, 0, 0)
+                    .map_err(|_| invalid())?;
+                let next = UtcDateTime::from_timespec(utc.unix_time() + SECONDS_PER_DAY, 0)
+                    .map_err(|_| invalid())?;
+                local(next.year(), next.month(), next.month_day(), (0, 0, 0))
+            }
+            None => local(parsed.year, parsed.month, parsed.day, (0, 0, 0)),
+        }
+    }
+}
+
+/// Components of a TOML date time.
+#[derive(Debug, PartialEq, Eq)]
+struct ParsedDatetime {
+    year: i32,
+    month: u8,
+    day: u8,
+    time: Option<(u8, u8, u8)>,
+    /// Offset from UTC in seconds.
+    offset: Option<i32>,
+}
+
+impl ParsedDatetime {
+    /// Parse the string representation of a [`Datetime`]. Times without a date are rejected.
+    fn parse(s: &str) -> Option<Self> {
+        let num = |s: &str| -> Option<u8> { s.parse().ok() };
+
+        let date = s.get(..10)?;
+        let mut parsed = Self {
+            year: date.get(..4)?.parse().ok()?,
+            month: num(date.get(5..7)?)?,
+            day: num(date.get(8..10)?)?,
+            time: None,
+            offset: None,
+        };
+
+        let rest = match s.get(10..)? {
+            "" => return Some(parsed),
+            r => r.strip_prefix('T')?,
+        };
+
+        parsed.time = Some((
+            num(rest.get(..2)?)?,
+            num(rest.get(3..5)?)?,
+            num(rest.get(6..8)?)?,
+        ));
+
+        // Sub-second precision is irrelevant here
+        let offset = rest
+            .get(8..)?
+            .trim_start_matches(|c: char| c == '.' || c.is_ascii_digit());
+
+        parsed.offset = match offset {
+            "" => None,
+            "Z" => Some(0),
+            o => {
+                let sign = match o.get(..1)? {
+                    "+" => 1,
+                    "-" => -1,
+                    _ => return None,
+                };
+                let h = i32::from(num(o.get(1..3)?)?);
+                let m = i32::from(num(o.get(4..6)?)?);
+                Some(sign * (h * 3600 + m * 60))
+            }
+        };
+
+        Some(parsed)
+    }
+}
+
+/// Days of the week.
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, Copy, PartialEq, Eq)]
+pub enum Weekday {
+    #[serde(rename = "mon", alias = "monday")]
+    Monday,
+    #[serde(rename = "tue", alias = "tuesday")]
+    Tuesday,
+    #[serde(rename = "wed", alias = "wednesday")]
+    Wednesday,
+    #[serde(rename = "thu", alias = "thursday")]
+    Thursday,
+    #[serde(rename = "fri", alias = "friday")]
+    Friday,
+    #[serde(rename = "sat", alias = "saturday")]
+    Saturday,
+    #[serde(rename = "sun", alias = "sunday")]
+    Sunday,
+}
+
+impl Weekday {
+    /// Get the weekday from the number of days since Sunday.
+    fn from_days_since_sunday(days: u8) -> Self {
+        match days % 7 {
+            0 => Self::Sunday,
+            1 => Self::Monday,
+            2 => Self::Tuesday,
+            3 => Self::Wednesday,
+            4 => Self::Thursday,
+            5 => Self::Friday,
+            _ => Self::Saturday,
+        }
+    }
+
+    /// Get the day before this one.
+    fn previous(self) -> Self {
+        match self {
+            Self::Monday => Self::Sunday,
+            Self::Tuesday => Self::Monday,
+            Self::Wednesday => Self::Tuesday,
+            Self::Thursday => Self::Wednesday,
+            Self::Friday => Self::Thursday,
+            Self::Saturday => Self::Friday,
+            Self::Sunday => Self::Saturday,
+        }
+    }
+}
+
+impl fmt::Display for Weekday {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Monday => "mon",
+            Self::Tuesday => "tue",
+            Self::Wednesday => "wed",
+            Self::Thursday => "thu",
+            Self::Friday => "fri",
+            Self::Saturday => "sat",
+            Self::Sunday => "sun",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A range of local time within a day, written as `"HH:MM-HH:MM"`.
+///
+/// The start is inclusive and the end is exclusive. Ranges whose end is before their start wrap
+/// around midnight, so `"22:00-06:00"` covers the night.
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(try_from = "String", into = "String")]
+pub struct HourRange {
+    /// Minutes since midnight.
+    start: u16,
+    /// Minutes since midnight.
+    end: u16,
+}
+
+impl HourRange {
+    /// Check if a time, in minutes since midnight, falls in this range.
+    #[must_use]
+    pub fn contains(&self, minutes: u16) -> bool {
+        if self.start <= self.end {
+            self.start <= minutes && minutes < self.end
+        } else {
+            self.start <= minutes || minutes < self.end
+        }
+    }
+}
+
+impl TryFrom<String> for HourRange {
+    type Error = String;
+
+    fn try_from(s: String) -> core::result::Result<Self, Self::Error> {
+        let parse_time = |t: &str| -> Option<u16> {
+            let (h, m) = t.trim().split_once(':')?;
+            let (h, m): (u16, u16) = (h.parse().ok()?, m.parse().ok()?);
+
+            match (h, m) {
+                (24, 0) => Some(24 * 60),
+                (0..=23, 0..=59) => Some(h * 60 + m),
+                _ => None,
+            }
+        };
+
+        s.split_once('-')
+            .and_then(|(start, end)| {
+                Some(Self {
+                    start: parse_time(start)?,
+                    end: parse_time(end)?,
+                })
+            })
+            .ok_or_else(|| format!("invalid hour range `{}`, expected `HH:MM-HH:MM`", s))
+    }
+}
+
+impl From<HourRange> for String {
+    fn from(r: HourRange) -> Self {
+        format!(
+            "{:02}:{:02}-{:02}:{:02}",
+            r.start / 60,
+            r.start % 60,
+            r.end / 60,
+            r.end % 60
+        )
+    }
+}
+
+impl fmt::Display for HourRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", String::from(*self))
+    }
+}
+
+/// A recurring window of local time.
+///
+/// ```toml
+/// [[policies.contractor.windows]]
+/// days = ["mon", "tue", "wed", "thu", "fri"]
+/// hours = "09:00-17:30"
+/// ```
+#[readonly::make]
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, PartialEq, Eq)]
+pub struct Window {
+    /// Days on which this window applies. Applies on all days if not specified.
+    #[serde(default = "defaults::days")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub days: Option<Vec<Weekday>>,
+    /// Range of time within each day. Covers the whole day if not specified.
+    #[serde(default = "defaults::hours")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hours: Option<HourRange>,
+}
+
+impl Window {
+    /// Check if a local day and time fall in this window.
+    ///
+    /// The part of a range that wraps around midnight belongs to the day it started on, so a
+    /// Friday window of `"22:00-02:00"` covers early Saturday.
+    #[must_use]
+    pub fn contains(&self, day: Weekday, minutes: u16) -> bool {
+        let on = |d: Weekday| self.days.as_ref().map_or(true, |days| days.contains(&d));
+
+        match self.hours {
+            Some(h) if h.start > h.end && minutes < h.end => on(day.previous()),
+            Some(h) => on(day) && h.contains(minutes),
+            None => on(day),
+        }
+    }
+}
+
+impl fmt::Display for Window {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.days {
+            Some(days) => {
+                let days: Vec<_> = days.iter().map(ToString::to_string).collect();
+                write!(f, "{}", days.join(","))?;
+            }
+            None => write!(f, "every day")?,
+        }
+
+        match self.hours {
+            Some(h) => write!(f, " {}", h),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Reasons for which a schedule is not active.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Inactive {
+    /// The schedule is not valid yet.
+    NotYetValid(Datetime),
+    /// The schedule has expired.
+    Expired(Datetime),
+    /// The current time is outside all windows.
+    OutsideWindows,
+    /// A date time could not be interpreted.
+    Invalid(Datetime),
+    /// Local times could not be interpreted, since the time zone could not be read.
+    UnknownTimeZone(String),
+}
+
+impl fmt::Display for Inactive {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotYetValid(d) => write!(f, "not valid before {}", d),
+            Self::Expired(d) => write!(f, "expired at {}", d),
+            Self::OutsideWindows => write!(f, "outside of its permitted time windows"),
+            Self::Invalid(d) => write!(f, "invalid date time {}", d),
+            Self::UnknownTimeZone(e) => write!(f, "not checkable without the time zone ({})", e),
+        }
+    }
+}
+
+/// Check that a configured date time can be interpreted, which requires a valid date.
+pub fn validate(datetime: &Datetime) -> core::result::Result<(), String> {
+    let valid = ParsedDatetime::parse(&datetime.to_string()).map_or(false, |p| {
+        let (h, m, s) = p.time.unwrap_or((0, 0, 0));
+        UtcDateTime::new(p.year, p.month, p.day, h, m, s, 0).is_ok()
+            && p.offset.map_or(true, |o| o.abs() < SECONDS_PER_DAY as i32)
+    });
+
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("invalid date time {}, expected a date", datetime))
+    }
+}
+
+/// Check if a schedule is active at some time.
+///
+/// A schedule is active from `valid_from` (inclusive) until `valid_until` (exclusive), within any
+/// of its `windows`. A `valid_until` date without a time includes the whole day.
+pub fn check(
+    valid_from: Option<&Datetime>,
+    valid_until: Option<&Datetime>,
+    windows: &[Window],
+    clock: &Clock,
+) -> core::result::Result<(), Inactive> {
+    if let Some(from) = valid_from {
+        if clock.now() < clock.resolve(from, false)? {
+            return Err(Inactive::NotYetValid(from.clone()));
+        }
+    }
+
+    if let Some(until) = valid_until {
+        if clock.now() >= clock.resolve(until, true)? {
+            return Err(Inactive::Expired(until.clone()));
+        }
+    }
+
+    if windows.is_empty() {
+        return Ok(());
+    }
+
+    let local = clock.local()?;
+    let day = Weekday::from_days_since_sunday(local.week_day());
+    let minutes = u16::from(local.hour()) * 60 + u16::from(local.minute());
+
+    if windows.iter().any(|w| w.contains(day, minutes)) {
+        Ok(())
+    } else {
+        Err(Inactive::OutsideWindows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Central European time, with daylight saving from the last Sunday of March to the last
+    /// Sunday of October.
+    fn cet(now: &str) -> Clock {
+        let tz = TimeZone::from_posix_tz("CET-1CEST,M3.5.0,M10.5.0/3").unwrap();
+        let utc = Clock::new(TimeZone::utc(), 0);
+        let now = utc.resolve(&now.parse().unwrap(), false).unwrap();
+        Clock::new(tz, now)
+    }
+
+    fn window(days: Option<Vec<Weekday>>, hours: &str) -> Window {
+        Window {
+            days,
+            hours: Some(HourRange::try_from(hours.to_owned()).unwrap()),
+        }
+    }
+
+    #[test]
+    fn test_parse_datetime() {
+        assert_eq!(
+            ParsedDatetime::parse("2026-03-29T02:30:00.5-07:30"),
+            Some(ParsedDatetime {
+                year: 2026,
+                month: 3,
+                day: 29,
+                time: Some((2, 30, 0)),
+                offset: Some(-(7 * 3600 + 30 * 60)),
+            })
+        );
+        assert_eq!(ParsedDatetime::parse("2026-03-29").unwrap().time, None);
+        assert_eq!(ParsedDatetime::parse("02:30:00"), None);
+
+        assert!(validate(&"2026-02-28T23:00:00+01:00".parse().unwrap()).is_ok());
+        assert!(validate(&"2026-02-30".parse().unwrap()).is_err());
+        assert!(validate(&"09:00:00".parse().unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_hour_range() {
+        let day = HourRange::try_from("09:00-17:30".to_owned()).unwrap();
+        assert!(day.contains(9 * 60));
+        assert!(day.contains(17 * 60 + 29));
+        assert!(!day.contains(17 * 60 + 30));
+
+        let night = HourRange::try_from("22:00-06:00".to_owned()).unwrap();
+        assert!(night.contains(23 * 60));
+        assert!(night.contains(60));
+        assert!(!night.contains(12 * 60));
+
+        assert_eq!(String::from(night), "22:00-06:00");
+        assert!(HourRange::try_from("9-5".to_owned()).is_err());
+        assert!(HourRange::try_from("25:00-26:00".to_owned()).is_err());
+    }
+
+    #[test]
+    fn test_validity_in_local_time() {
+        let until: Datetime = "2026-06-30".parse().unwrap();
+        let until_offset: Datetime = "2026-06-30T18:00:00Z".parse().unwrap();
+
+        // 23:30 local time on the last valid day
+        assert_eq!(
+            check(None, Some(&until), &[], &cet("2026-06-30T21:30:00Z")),
+            Ok(())
+        );
+        // 00:30 local time, but still the 30th in UTC
+        assert_eq!(
+            check(None, Some(&until), &[], &cet("2026-06-30T22:30:00Z")),
+            Err(Inactive::Expired(until.clone()))
+        );
+        // Offsets are absolute
+        assert_eq!(
+            check(None, Some(&until_offset), &[], &cet("2026-06-30T18:00:00Z")),
+            Err(Inactive::Expired(until_offset))
+        );
+
+        let from: Datetime = "2026-01-01T09:00:00".parse().unwrap();
+        assert_eq!(
+            check(Some(&from), None, &[], &cet("2026-01-01T07:59:59Z")),
+            Err(Inactive::NotYetValid(from.clone()))
+        );
+        assert_eq!(
+            check(Some(&from), None, &[], &cet("2026-01-01T08:00:00Z")),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_unknown_time_zone() {
+        let clock = Clock {
+            time_zone: Err("/etc/localtime: invalid".into()),
+            now: 0,
+        };
+        let unknown = Err(Inactive::UnknownTimeZone("/etc/localtime: invalid".into()));
+
+        // Only schedules that depend on local times are affected
+        let until_offset: Datetime = "2026-06-30T18:00:00Z".parse().unwrap();
+        assert_eq!(check(None, None, &[], &clock), Ok(()));
+        assert_eq!(check(None, Some(&until_offset), &[], &clock), Ok(()));
+
+        let until: Datetime = "2026-06-30".parse().unwrap();
+        assert_eq!(check(None, Some(&until), &[], &clock), unknown);
+        assert_eq!(
+            check(None, None, &[window(None, "09:00-17:30")], &clock),
+            unknown
+        );
+    }
+
+    #[test]
+    fn test_daylight_saving_transitions() {
+        // Skipped local times begin at the transition
+        let from: Datetime = "2026-03-29T02:30:00".parse().unwrap();
+        assert!(check(Some(&from), None, &[], &cet("2026-03-29T00:59:59Z")).is_err());
+        assert_eq!(
+            check(Some(&from), None, &[], &cet("2026-03-29T01:00:00Z")),
+            Ok(())
+        );
+
+        // Ambiguous local times resolve to the earliest instant
+        let from: Datetime = "2026-10-25T02:30:00".parse().unwrap();
+        assert!(check(Some(&from), None, &[], &cet("2026-10-25T00:29:59Z")).is_err());
+        assert_eq!(
+            check(Some(&from), None, &[], &cet("2026-10-25T00:30:00Z")),
+            Ok(())
+        );
+
+        // Windows follow the local wall clock
+        let windows = [window(None, "09:00-17:00")];
+        assert_eq!(
+            check(None, None, &windows, &cet("2026-01-15T08:00:00Z")),
+            Ok(())
+        );
+        assert_eq!(
+            check(None, None, &windows, &cet("2026-07-15T07:00:00Z")),
+            Ok(())
+        );
+        assert_eq!(
+            check(None, None, &windows, &cet("2026-07-15T15:00:00Z")),
+            Err(Inactive::OutsideWindows)
+        );
+    }
+
+    #[test]
+    fn test_weekday_windows() {
+        use Weekday::*;
+
+        let windows = [window(
+            Some(vec![Monday, Tuesday, Wednesday, Thursday, Friday]),
+            "09:00-17:30",
+        )];
+
+        // Friday, 2026-10-16 at 10:00 local time
+        assert_eq!(
+            check(None, None, &windows, &cet("2026-10-16T08:00:00Z")),
+            Ok(())
+        );
+        // Saturday, 2026-10-17 at 10:00 local time
+        assert_eq!(
+            check(None, None, &windows, &cet("2026-10-17T08:00:00Z")),
+            Err(Inactive::OutsideWindows)
+        );
+
+        // Late on Friday, until early on Saturday
+        let night = [window(Some(vec![Friday]), "22:00-02:00")];
+        assert_eq!(
+            check(None, None, &night, &cet("2026-10-16T21:00:00Z")),
+            Ok(())
+        );
+        assert_eq!(
+            check(None, None, &night, &cet("2026-10-16T23:00:00Z")),
+            Ok(())
+        );
+        assert_eq!(
+            check(None, None, &night, &cet("2026-10-17T21:00:00Z")),
+            Err(Inactive::OutsideWindows)
+        );
+        assert_eq!(
+            check(None, None, &night, &cet("2026-10-15T23:00:00Z")),
+            Err(Inactive::OutsideWindows)
+        );
+
+        let parsed: Window = toml::from_str("days = ['sat', 'sunday']").unwrap();
+        assert_eq!(parsed.days, Some(vec![Saturday, Sunday]));
+        assert!(parsed.contains(Sunday, 0));
+        assert!(!parsed.contains(Monday, 0));
+
+        assert_eq!(parsed.to_string(), "sat,sun");
+        assert_eq!(night[0].to_string(), "fri 22:00-02:00");
+        assert_eq!(
+            window(None, "09:00-17:30").to_string(),
+            "every day 09:00-17:30"
+        );
+    }
+}
@@ -1,5 +1,6 @@
 //! Authenticated session tools.
 
+use std::path::Path;
 use std::time::SystemTime;
 
 use nix::unistd::User;
@@ -53,7 +54,11 @@ impl UserSession {
         self.auth.get_user()
     }
 
-    /// Validate a user's account and run a function in an authenticated session.
+    /// Validate a user's account and run a function in an authenticated session, in which an
+    /// executable is run with the given arguments.
+    ///
+    /// Whether the user must authenticate depends on the executable, as well as on when the session
+    /// was last used (see [`Rules::auth_for`]).
     ///
     /// # Returns
     ///
@@ -66,14 +71,17 @@ impl UserSession {
     pub fn run<'a>(
         &mut self,
         target: &User,
+        path: &Path,
+        args: &[String],
         session: Box<dyn FnOnce() -> Result<()> + 'a>,
     ) -> Result<Result<()>> {
         // Check if the user needs to be re-validated
-        if !self.rules.no_auth {
+        let requirement = self.rules.auth_for(path, args);
+        if requirement != AuthRequirement::Never {
             let mut need_auth = true;
 
             // Check if the session has exceeded its timeout
-            if let Some(s) = self.state.last_used {
+            if let (AuthRequirement::Refresh, Some(s)) = (requirement, self.state.last_used) {
                 if let Ok(dur) = SystemTime::now().duration_since(s) {
                     if let Some(t) = self.rules.refresh {
                         need_auth = dur > t;
@@ -1,7 +1,9 @@
 //! User session configuration and state.
 
+use std::path::Path;
 use std::time::Duration;
 
+use crate::permits::CommandRule;
 use crate::prelude::*;
 
 /// Default field values.
@@ -17,9 +19,32 @@ pub(crate) mod defaults {
     pub const fn no_auth() -> bool {
         false
     }
+
+    #[inline]
+    pub const fn commands() -> Vec<CommandRule> {
+        Vec::new()
+    }
+}
+
+/// When the user must authenticate to run a command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthRequirement {
+    /// Never.
+    Never,
+    /// Unless the session was used within its `refresh` duration.
+    Refresh,
+    /// Every time.
+    Always,
 }
 
 /// Predefined rules for a user session.
+///
+/// ```toml
+/// [policies.default.session]
+/// refresh = 5
+/// no-auth-commands = [{ command = "/usr/bin/systemctl", args-prefix = ["status"] }]
+/// always-auth-commands = ["/usr/bin/passwd"]
+/// ```
 #[readonly::make]
 #[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
 pub struct Rules {
@@ -28,8 +53,21 @@ pub struct Rules {
     #[serde(default = "defaults::refresh")]
     pub refresh: Option<Duration>,
     /// Allow session to forego user validation.
+    #[serde(rename = "no-auth")]
+    #[serde(alias = "no_auth")]
     #[serde(default = "defaults::no_auth")]
     pub no_auth: bool,
+    /// Commands that never require authentication.
+    #[serde(rename = "no-auth-commands")]
+    #[serde(default = "defaults::commands")]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub no_auth_commands: Vec<CommandRule>,
+    /// Commands that require authentication every time, even within the `refresh` duration or
+    /// with `no-auth`. These take precedence over `no-auth-commands`.
+    #[serde(rename = "always-auth-commands")]
+    #[serde(default = "defaults::commands")]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub always_auth_commands: Vec<CommandRule>,
 }
 
 impl Default for Rules {
@@ -37,6 +75,8 @@ impl Default for Rules {
         Self {
             refresh: defaults::refresh(),
             no_auth: defaults::no_auth(),
+            no_auth_commands: defaults::commands(),
+            always_auth_commands: defaults::commands(),
         }
     }
 }
@@ -50,4 +90,124 @@ impl Rules {
             ..Self::default()
         }
     }
+
+    /// Combine these rules with another, keeping the strictest of both.
+    ///
+    /// Only commands that neither requires authentication for are exempt from it, while commands
+    /// that either always requires authentication for always require it.
+    #[must_use]
+    pub fn strictest(&self, other: &Self) -> Self {
+        let mut always_auth_commands = self.always_auth_commands.clone();
+        always_auth_commands.extend(
+            other
+                .always_auth_commands
+                .iter()
+                .filter(|c| !self.always_auth_commands.contains(c))
+                .cloned(),
+        );
+
+        Self {
+            refresh: match (self.refresh, other.refresh) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                _ => None,
+            },
+            no_auth: self.no_auth && other.no_auth,
+            no_auth_commands: self
+                .no_auth_commands
+                .iter()
+                .filter(|c| other.no_auth || other.no_auth_commands.contains(c))
+                .chain(
+                    other
+                        .no_auth_commands
+                        .iter()
+                        .filter(|c| self.no_auth && !self.no_auth_commands.contains(c)),
+                )
+                .cloned()
+                .collect(),
+            always_auth_commands,
+        }
+    }
+
+    /// Get the `always-auth-commands` rule matching an executable with the given arguments, if
+    /// any.
+    #[must_use]
+    pub fn always_auth_rule(&self, path: &Path, args: &[String]) -> Option<&CommandRule> {
+        self.always_auth_commands
+            .iter()
+            .find(|c| c.matches(path, args))
+    }
+
+    /// Get the `no-auth-commands` rule matching an executable with the given arguments, if any.
+    #[must_use]
+    pub fn no_auth_rule(&self, path: &Path, args: &[String]) -> Option<&CommandRule> {
+        self.no_auth_commands.iter().find(|c| c.matches(path, args))
+    }
+
+    /// Get when the user must authenticate to run an executable with the given arguments.
+    ///
+    /// `path` must be the absolute path of the executable.
+    #[must_use]
+    pub fn auth_for(&self, path: &Path, args: &[String]) -> AuthRequirement {
+        if self.always_auth_rule(path, args).is_some() {
+            AuthRequirement::Always
+        } else if self.no_auth || self.no_auth_rule(path, args).is_some() {
+            AuthRequirement::Never
+        } else if self.refresh.is_some() {
+            AuthRequirement::Refresh
+        } else {
+            AuthRequirement::Always
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_auth_for() {
+        let rules: Rules = toml::from_str(
+            "refresh = 5\n\
+             no-auth-commands = [{ command = '/usr/bin/systemctl', args-prefix = ['status'] }]\n\
+             always-auth-commands = ['/usr/bin/passwd']",
+        )
+        .unwrap();
+        let auth_for = |rules: &Rules, path: &str, args: &[&str]| {
+            let args: Vec<_> = args.iter().map(|a| a.to_string()).collect();
+            rules.auth_for(Path::new(path), &args)
+        };
+
+        assert_eq!(
+            auth_for(&rules, "/usr/bin/systemctl", &["status", "nginx"]),
+            AuthRequirement::Never
+        );
+        assert_eq!(
+            auth_for(&rules, "/usr/bin/systemctl", &["restart", "nginx"]),
+            AuthRequirement::Refresh
+        );
+        assert_eq!(
+            auth_for(&rules, "/usr/bin/passwd", &[]),
+            AuthRequirement::Always
+        );
+
+        let no_auth: Rules =
+            toml::from_str("no-auth = true\nalways-auth-commands = ['/usr/bin/passwd']").unwrap();
+        assert_eq!(
+            auth_for(&no_auth, "/usr/bin/id", &[]),
+            AuthRequirement::Never
+        );
+        assert_eq!(
+            auth_for(&no_auth, "/usr/bin/passwd", &[]),
+            AuthRequirement::Always
+        );
+
+        // Exemptions of one are kept if the other needs no authentication at all
+        let merged = rules.strictest(&no_auth);
+        assert_eq!(merged.no_auth_commands, rules.no_auth_commands);
+        assert_eq!(merged.always_auth_commands, rules.always_auth_commands);
+        assert!(rules
+            .strictest(&Rules::default())
+            .no_auth_commands
+            .is_empty());
+    }
 }
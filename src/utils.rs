@@ -2,8 +2,8 @@
 
 use std::fs::{self, File};
 use std::io::{self, BufRead, BufReader};
-use std::os::unix::fs::PermissionsExt;
-use std::path::Path;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::{Component, Path, PathBuf};
 use std::time::Duration;
 
 use mk_common::*;
@@ -21,6 +21,117 @@ pub fn get_path() -> String {
     )
 }
 
+/// Find the absolute path of an executable.
+///
+/// Commands containing a `/` are taken as paths relative to the current directory, otherwise each
+/// absolute directory in [`get_path`] is searched in order. The directory part of the result is
+/// canonicalized, while the file name is kept as is, so symbolic links to executables are not
+/// followed.
+#[must_use]
+pub fn find_executable(command: &str) -> Option<PathBuf> {
+    let resolve = |p: &Path| -> Option<PathBuf> {
+        let meta = fs::metadata(p).ok()?;
+        if !meta.is_file() || meta.permissions().mode() & 0o111 == 0 {
+            return None;
+        }
+
+        let name = p.file_name()?;
+        let dir = match p.parent() {
+            Some(d) if !d.as_os_str().is_empty() => fs::canonicalize(d).ok()?,
+            _ => std::env::current_dir().ok()?,
+        };
+
+        Some(dir.join(name))
+    };
+
+    if command.contains('/') {
+        return resolve(Path::new(command));
+    }
+
+    get_path()
+        .split(':')
+        .map(Path::new)
+        .filter(|d| d.is_absolute())
+        .find_map(|d| resolve(&d.join(command)))
+}
+
+/// Find the absolute path of an executable inside a root directory, such as a chroot.
+///
+/// Like [`find_executable`], except that commands containing a `/` must be absolute, and paths are
+/// never canonicalized. The result is the path of the executable inside `root`.
+#[must_use]
+pub fn find_executable_in(root: &Path, command: &str) -> Option<PathBuf> {
+    let is_executable = |p: &Path| {
+        p.strip_prefix("/")
+            .ok()
+            .and_then(|p| fs::metadata(root.join(p)).ok())
+            .map_or(false, |m| {
+                m.is_file() && m.permissions().mode() & 0o111 != 0
+            })
+    };
+
+    if command.contains('/') {
+        let path = Path::new(command);
+        return is_executable(path).then(|| path.to_path_buf());
+    }
+
+    get_path()
+        .split(':')
+        .map(Path::new)
+        .filter(|d| d.is_absolute())
+        .map(|d| d.join(command))
+        .find(|p| is_executable(p))
+}
+
+/// Check if a string matches a shell style wildcard pattern.
+///
+/// Wildcards never match a `/`, so `/usr/bin/*` matches `/usr/bin/id` but not `/usr/bin/x/id`.
+/// Invalid patterns never match anything.
+#[must_use]
+pub fn matches_pattern(pattern: &str, s: &str) -> bool {
+    let opts = glob::MatchOptions {
+        case_sensitive: true,
+        require_literal_separator: true,
+        require_literal_leading_dot: false,
+    };
+
+    glob::Pattern::new(pattern).map_or(false, |p| p.matches_with(s, opts))
+}
+
+/// Check if a host name matches any of the given patterns, ignoring case.
+///
+/// Every host matches if no patterns are specified.
+#[must_use]
+pub fn matches_any_host(patterns: Option<&[String]>, host: &str) -> bool {
+    let host = host.to_lowercase();
+
+    patterns.map_or(true, |p| {
+        p.iter().any(|p| matches_pattern(&p.to_lowercase(), &host))
+    })
+}
+
+/// Get all groups a user is a member of, including their primary group.
+///
+/// This is always empty on macOS.
+pub fn get_user_groups(user: &unistd::User) -> crate::Result<Vec<unistd::Group>> {
+    let mut groups = Vec::new();
+
+    // https://docs.rs/nix/0.22.1/nix/unistd/fn.getgrouplist.html
+    #[cfg(not(target_os = "macos"))]
+    for g in unistd::getgrouplist(
+        std::ffi::CString::new(&user.name[..])
+            .map_err(io::Error::from)?
+            .as_c_str(),
+        user.gid,
+    )? {
+        if let Some(grp) = unistd::Group::from_gid(g)? {
+            groups.push(grp);
+        }
+    }
+
+    Ok(groups)
+}
+
 /// Get the host name string.
 pub fn get_host_name() -> crate::Result<String> {
     let mut buf = [0_u8; 256];
@@ -31,6 +142,63 @@ pub fn get_host_name() -> crate::Result<String> {
     }
 }
 
+/// Make a path absolute and remove all `.` and `..` components, without accessing the file
+/// system.
+///
+/// This may refer to a different file than `path` if `path` contains symbolic links.
+#[must_use]
+pub fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::from("/");
+
+    if path.is_relative() {
+        if let Ok(d) = std::env::current_dir() {
+            normalized = d;
+        }
+    }
+
+    for c in path.components() {
+        match c {
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            Component::Normal(n) => normalized.push(n),
+            _ => {}
+        }
+    }
+
+    normalized
+}
+
+/// Check that a file and all its parent directories can only be modified by root.
+///
+/// Every component of `path` must be owned by root, must not be writable by its group or others,
+/// and must not be a symbolic link. `path` must be absolute and normalized (see
+/// [`normalize_path`]).
+pub fn check_secure_path(path: &Path) -> io::Result<()> {
+    let insecure = |p: &Path, reason: &str| {
+        io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{} {}", p.display(), reason),
+        )
+    };
+
+    for p in path.ancestors() {
+        let meta = fs::symlink_metadata(p)?;
+
+        if meta.file_type().is_symlink() {
+            return Err(insecure(p, "is a symbolic link"));
+        }
+        if meta.uid() != 0 {
+            return Err(insecure(p, "is not owned by root"));
+        }
+        if meta.mode() & 0o022 != 0 {
+            return Err(insecure(p, "is writable by group or others"));
+        }
+    }
+
+    Ok(())
+}
+
 /// Change a given file's mode.
 pub fn set_mode<P: AsRef<Path>>(path: P, mode: u32) -> io::Result<()> {
     let mut perms = fs::metadata(path.as_ref())?.permissions();
@@ -69,6 +237,42 @@ pub mod timeout_serializer {
     }
 }
 
+/// A value written either as a string, or as a table deserialized into `T`.
+pub enum StringOrTable<T> {
+    String(String),
+    Table(T),
+}
+
+impl<'de, T: serde::Deserialize<'de>> serde::Deserialize<'de> for StringOrTable<T> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor<T>(std::marker::PhantomData<T>);
+
+        impl<'de, T: serde::Deserialize<'de>> serde::de::Visitor<'de> for Visitor<T> {
+            type Value = StringOrTable<T>;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                write!(f, "a string or a table")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                Ok(StringOrTable::String(v.to_owned()))
+            }
+
+            fn visit_map<A: serde::de::MapAccess<'de>>(
+                self,
+                map: A,
+            ) -> Result<Self::Value, A::Error> {
+                // Deserialize directly from `map`, so that wrapping deserializers still see every
+                // key of the table
+                T::deserialize(serde::de::value::MapAccessDeserializer::new(map))
+                    .map(StringOrTable::Table)
+            }
+        }
+
+        deserializer.deserialize_any(Visitor(std::marker::PhantomData))
+    }
+}
+
 /// Read a password from the tty.
 macro_rules! password_from_tty {
     ($($arg:tt)*) => {